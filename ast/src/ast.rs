@@ -1,47 +1,222 @@
-use rug::{ Integer, Float };
+use rug::{ Integer, Rational };
 
-/// Precision of floats read into Micron
+use crate::location::Span;
+
+/// Precision of floats read into Micron, unless the engine is set to something else
 pub const FLOAT_PRECISION: u32 = 53;
 pub const RADIX: i32 = 10;
 
 #[derive(Debug, Clone)]
 pub enum VariableType {
    Singular(String),
-   Nested(String, Vec<DictAccessType>),
+
+   // name[key][key], each key is an expression
+   Nested(String, Vec<Box<Expr>>),
 }
 
 #[derive(Debug, Clone)]
 pub enum Statement {
 
+    // Statement along with where it came from in the source
+    Located(Span, Box<Statement>),
+
     ScopedStatementBlock(Vec<Box<Statement>>),
     Assignment(VariableType, Box<Expr>),
     BareExpression(Box<Expr>),
     Yield(Box<Expr>),
+
+    // while <expr> { .. }
+    While(Box<Expr>, Vec<Box<Statement>>),
+
+    // for a in <iterable> { .. } or for a, b in <iterable> { .. }
+    For(Vec<String>, Iterable, Vec<Box<Statement>>),
+    Break,
+    Continue,
+
+    // fn name(a, b) { .. }
+    FunctionDefinition(String, Vec<String>, Vec<Box<Statement>>),
+    Return(Box<Expr>),
 }
 
 #[derive(Debug, Clone)]
 pub enum Expr {
+
+    // Expression along with where it came from in the source
+    Located(Span, Box<Expr>),
+
     Number(Integer),
-    Real(Float),
+
+    // Floats are kept as they were written, so they can be read in at 
+    // whatever precision the engine is set to when they are used
+    Real(String),
+
+    // 3r, an exact fraction
+    Rational(Rational),
+
+    // 3i or 2.5i, an imaginary number. Kept as written, the same as floats
+    Imaginary(String),
+    Bool(bool),
+    Nil,
     String(String),
     Variable(VariableType),
 
+    // f"text {expr:spec}", the pieces of text and the values placed between them
+    Format(Vec<FormatPart>),
+
     Op(Box<Expr>, Opcode, Box<Expr>),
     UnaryOp(Box<Expr>, UnaryOpcode),
 
 
-    BuiltInModifierCall(String, String),
+    // #name(a, b)
+    BuiltInCall(String, Vec<Box<Expr>>),
+
+    // name(a, b)
+    Call(String, Vec<Box<Expr>>),
 
     Access(Box<Expr>, Accessors, Box<MemberMethod>),
 
     // { .. }
     Dict(Vec<Box<DictEntry>>),
 
+    // [ .. ]
+    List(Vec<Box<Expr>>),
+
     IfExpression(Box<Vec<ConditionalBlock>>)
 }
 
 
-#[derive(Debug, Clone)]
+impl Statement {
+
+    /// The statement without any location information wrapped around it
+    pub fn unlocated(&self) -> &Statement {
+        match self {
+            Statement::Located(_, statement) => statement.unlocated(),
+            statement => statement
+        }
+    }
+}
+
+impl Expr {
+
+    /// Wrap an expression with the location it was parsed from
+    pub fn located(start: usize, end: usize, expr: Expr) -> Box<Expr> {
+        Box::new(Expr::Located(Span::new(start, end), Box::new(expr)))
+    }
+
+    /// The expression without any location information wrapped around it
+    pub fn unlocated(&self) -> &Expr {
+        match self {
+            Expr::Located(_, expr) => expr.unlocated(),
+            expr => expr
+        }
+    }
+
+    /// Move every location within the expression forward, for an expression that was parsed
+    /// out of a piece of the source rather than the whole thing
+    pub fn shift(&mut self, offset: usize) {
+        match self {
+            Expr::Located(span, expr) => {
+                *span = Span::new(span.start + offset, span.end + offset);
+                expr.shift(offset);
+            }
+            Expr::Variable(variable) => { variable.shift(offset); }
+            Expr::Format(parts) => {
+                for part in parts {
+                    if let FormatPart::Value(expr, _) = part {
+                        expr.shift(offset);
+                    }
+                }
+            }
+            Expr::Op(lhs, _, rhs) => {
+                lhs.shift(offset);
+                rhs.shift(offset);
+            }
+            Expr::UnaryOp(expr, _) => { expr.shift(offset); }
+            Expr::BuiltInCall(_, params) | Expr::Call(_, params) | Expr::List(params) => {
+                for param in params {
+                    param.shift(offset);
+                }
+            }
+            Expr::Access(expr, _, method) => {
+                expr.shift(offset);
+                for param in method.params.iter_mut() {
+                    param.shift(offset);
+                }
+            }
+            Expr::Dict(entries) => {
+                for entry in entries {
+                    entry.key.shift(offset);
+                    entry.value.shift(offset);
+                }
+            }
+            Expr::IfExpression(blocks) => {
+                for block in blocks.iter_mut() {
+                    if let Some(expr) = &mut block.expression {
+                        expr.shift(offset);
+                    }
+                    shift_all(&mut block.body, offset);
+                }
+            }
+            Expr::Number(_) | Expr::Real(_) | Expr::Rational(_) | Expr::Imaginary(_) |
+            Expr::Bool(_) | Expr::Nil | Expr::String(_) => { }
+        }
+    }
+}
+
+impl VariableType {
+
+    /// Move the locations within the keys of the variable forward
+    pub fn shift(&mut self, offset: usize) {
+        if let VariableType::Nested(_, keys) = self {
+            for key in keys {
+                key.shift(offset);
+            }
+        }
+    }
+}
+
+impl Statement {
+
+    /// Move every location within the statement forward
+    pub fn shift(&mut self, offset: usize) {
+        match self {
+            Statement::Located(span, statement) => {
+                *span = Span::new(span.start + offset, span.end + offset);
+                statement.shift(offset);
+            }
+            Statement::ScopedStatementBlock(body) | Statement::FunctionDefinition(_, _, body) => { shift_all(body, offset); }
+            Statement::Assignment(variable, expr) => {
+                variable.shift(offset);
+                expr.shift(offset);
+            }
+            Statement::BareExpression(expr) | Statement::Yield(expr) | Statement::Return(expr) => { expr.shift(offset); }
+            Statement::While(condition, body) => {
+                condition.shift(offset);
+                shift_all(body, offset);
+            }
+            Statement::For(_, iterable, body) => {
+                match iterable {
+                    Iterable::Expression(expr) => { expr.shift(offset); }
+                    Iterable::Range(start, end, _) => {
+                        start.shift(offset);
+                        end.shift(offset);
+                    }
+                }
+                shift_all(body, offset);
+            }
+            Statement::Break | Statement::Continue => { }
+        }
+    }
+}
+
+/// Move the locations within each of the statements forward
+fn shift_all(statements: &mut [Box<Statement>], offset: usize) {
+    for statement in statements {
+        statement.shift(offset);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Opcode {
     Mul,
     Div,
@@ -64,16 +239,11 @@ pub enum Opcode {
     And
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UnaryOpcode {
     Negate,
-    BwNot
-}
-
-#[derive(Debug, Clone)]
-pub enum DictAccessType {
-    RawValue(String),
-    Variable(String)
+    BwNot,
+    Minus
 }
 
 #[derive(Debug, Clone)]
@@ -89,10 +259,71 @@ pub struct MemberMethod {
 
 #[derive(Debug, Clone)]
 pub struct DictEntry {
-    pub key: String,
+    pub key: Box<Expr>,
     pub value: Box<Expr>
 }
 
+/// What a for loop walks over
+#[derive(Debug, Clone)]
+pub enum Iterable {
+
+    // A list, dict or string
+    Expression(Box<Expr>),
+
+    // start..end, or start..=end when the end is included
+    Range(Box<Expr>, Box<Expr>, bool)
+}
+
+/// A piece of an interpolated string
+#[derive(Debug, Clone)]
+pub enum FormatPart {
+    Text(String),
+    Value(Box<Expr>, FormatSpec)
+}
+
+/// How a value placed in an interpolated string is written out, everything after the ':' in {x:>8.2}
+#[derive(Debug, Clone)]
+pub struct FormatSpec {
+
+    // Character used to pad out to the width
+    pub fill: char,
+
+    // Unaligned numbers go to the right and everything else to the left
+    pub align: Option<Alignment>,
+
+    // Pad numbers with zeros placed after the sign
+    pub zero: bool,
+    pub width: Option<usize>,
+
+    // Digits after the decimal point, or the most characters of a string
+    pub precision: Option<usize>,
+
+    // Write an integer in another radix, :x :X :o or :b
+    pub radix: Option<i32>,
+    pub uppercase: bool
+}
+
+impl Default for FormatSpec {
+    fn default() -> Self {
+        FormatSpec {
+            fill: ' ',
+            align: None,
+            zero: false,
+            width: None,
+            precision: None,
+            radix: None,
+            uppercase: false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center
+}
+
 #[derive(Debug, Clone)]
 pub struct ConditionalBlock {
     pub expression: Option<Box<Expr>>,
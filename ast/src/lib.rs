@@ -1,3 +1,5 @@
 
 mod ast;
-pub use ast::*;
\ No newline at end of file
+pub use ast::*;
+mod location;
+pub use location::*;
@@ -0,0 +1,68 @@
+/// Byte offsets into the source that a statement or expression was parsed from
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+impl Span {
+
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Where the first character of the span is. A '(' or '[' that starts a line is read in
+    /// along with the whitespace before it, so a span can begin on that whitespace
+    fn first_character(&self, source: &str) -> usize {
+
+        let start = self.start.min(source.len());
+        let rest = source[start..].trim_start();
+
+        if rest.is_empty() {
+            return start;
+        }
+        source.len() - rest.len()
+    }
+
+    /// Line and column that the span starts on, both counted from 1
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+
+        let start = self.first_character(source);
+
+        let before = &source[..start];
+
+        let line = before.matches('\n').count() + 1;
+
+        let column = match before.rfind('\n') {
+            Some(newline) => { before[newline + 1..].chars().count() + 1 }
+            None          => { before.chars().count() + 1 }
+        };
+
+        (line, column)
+    }
+
+    /// Render a message as 'file:line:col: message' followed by the line of source 
+    /// the span starts on, with the span underlined
+    pub fn render(&self, file: &str, source: &str, message: &str) -> String {
+
+        let (line, column) = self.line_col(source);
+
+        let line_text = source.lines().nth(line - 1).unwrap_or("");
+
+        // Only underline up to the end of the first line
+        let line_length = line_text.chars().count();
+        let start = self.first_character(source);
+        let underline_length = source[start..self.end.max(start).min(source.len())]
+                                .chars()
+                                .count()
+                                .min(line_length.saturating_sub(column - 1))
+                                .max(1);
+
+        let gutter = line.to_string();
+
+        format!("{}:{}:{}: {}\n{} | {}\n{} | {}{}", 
+            file, line, column, message,
+            gutter, line_text,
+            " ".repeat(gutter.len()), " ".repeat(column - 1), "^".repeat(underline_length))
+    }
+}
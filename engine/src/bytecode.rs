@@ -0,0 +1,113 @@
+/*
+
+    Bytecode run by the virtual machine. Statements are compiled into chunks of instructions that
+    work on an operand stack. Values that are known ahead of time live in a constant pool and 
+    variables are resolved to slots so nothing has to be looked up by name while running
+*/
+
+use micron_ast::{ Opcode, UnaryOpcode, Span, FormatSpec };
+
+use crate::types::{ RecordData, Function };
+use crate::error::ExecutionError;
+
+/// A single instruction for the virtual machine
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Instruction {
+
+    // Push an item from the constant pool
+    Constant(usize),
+
+    // Read in the digits of a float or imaginary number at the precision of the machine
+    Real(usize),
+    Imaginary(usize),
+
+    // Write the top of the stack out as a string with a format spec
+    Format(usize),
+
+    // Join the top strings on the stack into one
+    Concat(usize),
+
+    // Push the result of an operation that didn't produce anything
+    Nothing,
+
+    // Variables by slot, nested variables also take the amount of keys on the stack above the value
+    Load(usize),
+    LoadNested(usize, usize),
+    Store(usize),
+    StoreNested(usize, usize),
+    Drop(usize),
+
+    // Build a value out of the items on the stack. Dictionaries take the amount of key and value pairs
+    MakeDict(usize),
+    MakeList(usize),
+    MakeFunction(usize),
+
+    Binary(Opcode),
+    Unary(UnaryOpcode),
+
+    // Method name and argument count
+    Method(usize, usize),
+
+    // Call the function below the given amount of arguments
+    Call(usize),
+
+    // Host function name and argument count
+    BuiltIn(usize, usize),
+
+    // Finish a bare expression statement
+    Echo,
+
+    // Hand a value out of the current scope
+    Yield,
+    Yielded,
+
+    // Leave the current function with the value on the stack, nothing, or what was yielded
+    Return,
+    ReturnNothing,
+    ReturnYielded,
+
+    Jump(usize),
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+
+    // For loops. The iteration sits on the stack while the loop runs and Next pushes the following 
+    // item, or jumps once there are none left. Iterate is told if the loop takes a key and value
+    Iterate(bool),
+    Range(bool),
+    Next(usize),
+    Unpack,
+    Pop,
+
+    // Scopes. A mark remembers the scope depth and stack size so they can be restored later
+    EnterScope,
+    Mark,
+    Restore,
+    Unmark(usize),
+
+    // Jump if a yield has left the scope of the innermost mark
+    CheckScope(usize),
+
+    Fail(fn() -> ExecutionError)
+}
+
+/// Compiled code along with everything it refers to
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Chunk {
+    pub(crate) code: Vec<Instruction>,
+
+    /// Location in the source that each instruction came from
+    pub(crate) spans: Vec<Option<Span>>,
+
+    pub(crate) constants: Vec<RecordData>,
+    pub(crate) names: Vec<String>,
+
+    /// Digits of float and imaginary literals
+    pub(crate) digits: Vec<String>,
+
+    /// Format specs of the values placed in interpolated strings
+    pub(crate) formats: Vec<FormatSpec>,
+    pub(crate) functions: Vec<Function>,
+
+    /// For functions, the global slot each local slot falls back on. The first slots are the parameters
+    pub(crate) globals: Vec<usize>
+}
@@ -0,0 +1,243 @@
+
+/*
+    Comparing items. Nothing is promoted before it is compared, so integers, rationals and floats
+    are compared by their exact values. An integer too large for the precision of a float is
+    still never equal to that float unless the two really are the same number.
+
+    Equality is structural. Lists are equal when their items are, dictionaries when they have
+    the same keys holding equal values. Items of different types are simply not equal, apart
+    from numbers, which are equal whenever their values are.
+
+    Only numbers and strings can be ordered with '<' and the like. Sorting uses a total order
+    instead, which puts any two items in order no matter their types:
+
+        nil < bools < numbers < strings < lists < dictionaries < functions
+
+    Numbers are ordered by value, complex numbers by their real part and then their imaginary
+    part, and NaN comes after every other number. Lists are ordered item by item and
+    dictionaries key by key, in the order their keys are walked
+*/
+
+use std::cmp::Ordering;
+use rug::{ Integer, Float, Rational };
+
+use crate::types::RecordData;
+use crate::error::ExecutionError;
+use micron_ast::Opcode;
+
+/// A real number borrowed out of an item, or out of one part of a complex number
+#[derive(Clone, Copy)]
+enum Real<'a> {
+    Integer(&'a Integer),
+    Rational(&'a Rational),
+    Float(&'a Float)
+}
+
+/// Check if two items are equal
+pub(crate) fn equal(lhs: &RecordData, rhs: &RecordData) -> bool {
+
+    if let (Some(lhs), Some(rhs)) = (number(lhs), number(rhs)) {
+        return parts(lhs.0, rhs.0) == Some(Ordering::Equal) && parts(lhs.1, rhs.1) == Some(Ordering::Equal);
+    }
+
+    match (lhs, rhs) {
+        (RecordData::Nil, RecordData::Nil)             => { true }
+        (RecordData::Bool(lhs), RecordData::Bool(rhs)) => { lhs == rhs }
+        (RecordData::String(lhs), RecordData::String(rhs)) => { lhs == rhs }
+
+        (RecordData::List(lhs), RecordData::List(rhs)) => {
+
+            if lhs.shares(rhs) {
+                return true;
+            }
+
+            lhs.len() == rhs.len() && lhs.iter().zip(rhs.iter()).all(|(lhs, rhs)| equal(&lhs.borrow(), &rhs.borrow()))
+        }
+
+        (RecordData::Dict(lhs), RecordData::Dict(rhs)) => {
+
+            if lhs.shares(rhs) {
+                return true;
+            }
+
+            lhs.len() == rhs.len() && lhs.entries().iter().all(|(key, lhs)| {
+                match rhs.get(key) {
+                    Some(rhs) => { equal(&lhs.borrow(), &rhs.borrow()) }
+                    None => { false }
+                }
+            })
+        }
+
+        // A function is only equal to another handle to the same definition
+        (RecordData::Function(lhs), RecordData::Function(rhs)) => {
+            lhs.name == rhs.name && std::rc::Rc::ptr_eq(&lhs.body, &rhs.body)
+        }
+
+        _ => { false }
+    }
+}
+
+/// Order two items for '<', '<=', '>' and '>='. Anything compared against NaN is false
+pub(crate) fn order(lhs: &RecordData, rhs: &RecordData, op: Opcode) -> Result<bool, ExecutionError> {
+
+    let ordering = match (lhs, rhs) {
+
+        (RecordData::String(lhs), RecordData::String(rhs)) => { Some(lhs.cmp(rhs)) }
+
+        (RecordData::Complex(_), _) | (_, RecordData::Complex(_)) if number(lhs).is_some() && number(rhs).is_some() => {
+            return Err(ExecutionError::InvalidOperation("Complex numbers can not be ordered"));
+        }
+
+        (RecordData::String(_), _) | (_, RecordData::String(_)) => {
+            return Err(ExecutionError::InvalidOperation("Strings can only be ordered against other strings"));
+        }
+
+        _ => {
+            match (number(lhs), number(rhs)) {
+                (Some(lhs), Some(rhs)) => { parts(lhs.0, rhs.0) }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Only numbers and strings can be ordered, use '.sort()' to put other items in order"));
+                }
+            }
+        }
+    };
+
+    let ordering = match ordering {
+        Some(ordering) => { ordering }
+        None => { return Ok(false); }
+    };
+
+    match op {
+        Opcode::Lt  => { Ok(ordering == Ordering::Less) }
+        Opcode::Lte => { Ok(ordering != Ordering::Greater) }
+        Opcode::Gt  => { Ok(ordering == Ordering::Greater) }
+        Opcode::Gte => { Ok(ordering != Ordering::Less) }
+        _ => {
+            Err(ExecutionError::InvalidOperation("Expected an ordering operation"))
+        }
+    }
+}
+
+/// Put any two items in order. Numbers that are equal in value are equal here as well,
+/// whatever their types, so sorting keeps them in the order they were in
+pub(crate) fn total(lhs: &RecordData, rhs: &RecordData) -> Ordering {
+
+    let ordering = rank(lhs).cmp(&rank(rhs));
+
+    if ordering != Ordering::Equal {
+        return ordering;
+    }
+
+    if let (Some(lhs), Some(rhs)) = (number(lhs), number(rhs)) {
+        return total_parts(lhs.0, rhs.0).then_with(|| total_parts(lhs.1, rhs.1));
+    }
+
+    match (lhs, rhs) {
+        (RecordData::Bool(lhs), RecordData::Bool(rhs))     => { lhs.cmp(rhs) }
+        (RecordData::String(lhs), RecordData::String(rhs)) => { lhs.cmp(rhs) }
+
+        (RecordData::List(lhs), RecordData::List(rhs)) => {
+
+            for (lhs, rhs) in lhs.iter().zip(rhs.iter()) {
+
+                let ordering = total(&lhs.borrow(), &rhs.borrow());
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            lhs.len().cmp(&rhs.len())
+        }
+
+        (RecordData::Dict(lhs), RecordData::Dict(rhs)) => {
+
+            let (lhs, rhs) = (lhs.entries(), rhs.entries());
+
+            for ((lhs_key, lhs), (rhs_key, rhs)) in lhs.iter().zip(rhs.iter()) {
+
+                let ordering = lhs_key.cmp(rhs_key).then_with(|| total(&lhs.borrow(), &rhs.borrow()));
+
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            lhs.len().cmp(&rhs.len())
+        }
+
+        (RecordData::Function(lhs), RecordData::Function(rhs)) => { lhs.name.cmp(&rhs.name) }
+
+        _ => { Ordering::Equal }
+    }
+}
+
+/// Where each type of item falls in the total order
+fn rank(item: &RecordData) -> u8 {
+    match item {
+        RecordData::Nil         => { 0 }
+        RecordData::Bool(_)     => { 1 }
+        RecordData::Integer(_) | RecordData::Rational(_) | RecordData::Float(_) | RecordData::Complex(_) => { 2 }
+        RecordData::String(_)   => { 3 }
+        RecordData::List(_)     => { 4 }
+        RecordData::Dict(_)     => { 5 }
+        RecordData::Function(_) => { 6 }
+    }
+}
+
+/// The real and imaginary parts of a number. Real numbers have no imaginary part, which is
+/// the same as an imaginary part of zero
+fn number(item: &RecordData) -> Option<(Option<Real<'_>>, Option<Real<'_>>)> {
+    match item {
+        RecordData::Integer(v)  => { Some((Some(Real::Integer(v)), None)) }
+        RecordData::Rational(v) => { Some((Some(Real::Rational(v)), None)) }
+        RecordData::Float(v)    => { Some((Some(Real::Float(v)), None)) }
+        RecordData::Complex(v)  => { Some((Some(Real::Float(v.real())), Some(Real::Float(v.imag())))) }
+        _ => { None }
+    }
+}
+
+/// Compare one part of two numbers exactly, a missing part is zero. There is no ordering when NaN is involved
+fn parts(lhs: Option<Real>, rhs: Option<Real>) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => { real(lhs, rhs) }
+        (Some(lhs), None)      => { sign(lhs) }
+        (None, Some(rhs))      => { sign(rhs).map(Ordering::reverse) }
+        (None, None)           => { Some(Ordering::Equal) }
+    }
+}
+
+/// Compare one part of two numbers with NaN placed after every other number
+fn total_parts(lhs: Option<Real>, rhs: Option<Real>) -> Ordering {
+    match parts(lhs, rhs) {
+        Some(ordering) => { ordering }
+        None => { is_nan(lhs).cmp(&is_nan(rhs)) }
+    }
+}
+
+/// Compare two real numbers by their exact values
+fn real(lhs: Real, rhs: Real) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Real::Integer(lhs), Real::Integer(rhs))   => { Some(lhs.cmp(rhs)) }
+        (Real::Integer(lhs), Real::Rational(rhs))  => { lhs.partial_cmp(rhs) }
+        (Real::Integer(lhs), Real::Float(rhs))     => { lhs.partial_cmp(rhs) }
+        (Real::Rational(lhs), Real::Integer(rhs))  => { lhs.partial_cmp(rhs) }
+        (Real::Rational(lhs), Real::Rational(rhs)) => { Some(lhs.cmp(rhs)) }
+        (Real::Rational(lhs), Real::Float(rhs))    => { lhs.partial_cmp(rhs) }
+        (Real::Float(lhs), Real::Integer(rhs))     => { lhs.partial_cmp(rhs) }
+        (Real::Float(lhs), Real::Rational(rhs))    => { lhs.partial_cmp(rhs) }
+        (Real::Float(lhs), Real::Float(rhs))       => { lhs.partial_cmp(rhs) }
+    }
+}
+
+/// Compare a real number against zero
+fn sign(value: Real) -> Option<Ordering> {
+    match value {
+        Real::Integer(v)  => { Some(v.cmp0()) }
+        Real::Rational(v) => { Some(v.cmp0()) }
+        Real::Float(v)    => { v.cmp0() }
+    }
+}
+
+/// Check if one part of a number is NaN
+fn is_nan(value: Option<Real>) -> bool {
+    matches!(value, Some(Real::Float(v)) if v.is_nan())
+}
@@ -0,0 +1,730 @@
+/*
+
+    Compiles statements into bytecode for the virtual machine.
+
+    Variables are resolved to slots here. Outside of functions a slot is the variable's index in
+    the global table, inside of a function every name used gets its own local slot that falls back
+    on the global slot of the same name, mirroring how functions can see globals in the engine.
+
+    Scoping works the same way it does in the engine. Blocks get a scope that is thrown away when
+    they finish, and a yield hands a value out of the innermost scope
+*/
+
+use std::{ collections::HashMap, rc::Rc };
+
+use micron_ast::{
+    Statement,
+    Expr,
+    Opcode,
+    VariableType,
+    ConditionalBlock,
+    Iterable,
+    Accessors,
+    Span,
+    FormatPart
+};
+
+use crate::bytecode::{ Chunk, Instruction };
+use crate::types::{ RecordData, Function };
+use crate::error::ExecutionError;
+
+/// A loop being compiled
+struct Loop {
+
+    /// Marks that exist once the loop has been entered
+    marks: usize,
+
+    /// Instructions that jump out of the loop, to be pointed at the end once it is known
+    exits: Vec<usize>,
+
+    /// Where the condition starts
+    condition: usize
+}
+
+/// Compiler for a single chunk
+pub(crate) struct Compiler<'a> {
+
+    /// Global names and their slots, shared with the virtual machine
+    symbols: &'a mut HashMap<String, usize>,
+
+    /// Local names and their slots if this is a function
+    locals: Option<HashMap<String, usize>>,
+
+    chunk: Chunk,
+    loops: Vec<Loop>,
+    marks: usize,
+    span: Option<Span>
+}
+
+impl<'a> Compiler<'a> {
+
+    /// Compile top level statements
+    pub(crate) fn compile(symbols: &'a mut HashMap<String, usize>, statements: &[Box<Statement>]) -> Chunk {
+
+        let mut compiler = Compiler {
+            symbols,
+            locals: None,
+            chunk: Chunk::default(),
+            loops: Vec::new(),
+            marks: 0,
+            span: None
+        };
+
+        for statement in statements {
+            compiler.statement(statement);
+        }
+
+        compiler.chunk
+    }
+
+    /// Compile the body of a function
+    fn compile_function(symbols: &'a mut HashMap<String, usize>, params: &[String], body: &[Box<Statement>]) -> Chunk {
+
+        // The call itself leaves a mark at the start of the function
+        let mut compiler = Compiler {
+            symbols,
+            locals: Some(HashMap::new()),
+            chunk: Chunk::default(),
+            loops: Vec::new(),
+            marks: 1,
+            span: None
+        };
+
+        for param in params {
+            compiler.slot(param);
+        }
+
+        // A yield at the top of the function leaves it with the yielded value
+        let mut yield_checks = Vec::new();
+
+        for statement in body {
+            compiler.statement(statement);
+
+            if yields(statement) {
+                yield_checks.push(compiler.emit(Instruction::CheckScope(0)));
+            }
+        }
+
+        compiler.emit(Instruction::ReturnNothing);
+
+        let yielded = compiler.here();
+        compiler.patch_all(yield_checks, yielded);
+        compiler.emit(Instruction::ReturnYielded);
+
+        compiler.chunk
+    }
+
+    /// Add an instruction, returning where it was placed
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.chunk.code.push(instruction);
+        self.chunk.spans.push(self.span);
+        self.chunk.code.len() - 1
+    }
+
+    /// Where the next instruction will be placed
+    fn here(&self) -> usize {
+        self.chunk.code.len()
+    }
+
+    /// Point a jump at the given target
+    fn patch(&mut self, at: usize, target: usize) {
+        self.chunk.code[at] = match self.chunk.code[at] {
+            Instruction::Jump(_)        => { Instruction::Jump(target) }
+            Instruction::JumpIfFalse(_) => { Instruction::JumpIfFalse(target) }
+            Instruction::JumpIfTrue(_)  => { Instruction::JumpIfTrue(target) }
+            Instruction::CheckScope(_)  => { Instruction::CheckScope(target) }
+            Instruction::Next(_)        => { Instruction::Next(target) }
+            instruction => { instruction }
+        };
+    }
+
+    fn patch_all(&mut self, jumps: Vec<usize>, target: usize) {
+        for at in jumps {
+            self.patch(at, target);
+        }
+    }
+
+    /// Add a constant to the pool
+    fn constant(&mut self, value: RecordData) -> usize {
+        self.chunk.constants.push(value);
+        self.chunk.constants.len() - 1
+    }
+
+    /// Add the digits of a number
+    fn digits(&mut self, digits: &str) -> usize {
+        self.chunk.digits.push(digits.to_string());
+        self.chunk.digits.len() - 1
+    }
+
+    /// Add a method or function name
+    fn name(&mut self, name: &str) -> usize {
+        self.chunk.names.push(name.to_string());
+        self.chunk.names.len() - 1
+    }
+
+    /// Get the slot of a global variable, creating it if it doesn't exist yet
+    fn global(&mut self, name: &str) -> usize {
+        let next = self.symbols.len();
+        *self.symbols.entry(name.to_string()).or_insert(next)
+    }
+
+    /// Get the slot of a variable
+    fn slot(&mut self, name: &str) -> usize {
+
+        let local = match &self.locals {
+            None => { return self.global(name); }
+            Some(locals) => { locals.get(name).copied() }
+        };
+
+        match local {
+            Some(slot) => { slot }
+            None => {
+                let global = self.global(name);
+                self.chunk.globals.push(global);
+
+                let slot = self.chunk.globals.len() - 1;
+                if let Some(locals) = &mut self.locals {
+                    locals.insert(name.to_string(), slot);
+                }
+                slot
+            }
+        }
+    }
+
+    /// Leave each key of the path into a nested variable on the stack, giving back how many there are
+    fn path(&mut self, accessors: &[Box<Expr>]) -> usize {
+
+        for accessor in accessors {
+            self.expression(accessor);
+        }
+        accessors.len()
+    }
+
+    /// Compile a statement
+    fn statement(&mut self, statement: &Statement) {
+
+        match statement {
+
+            Statement::Located(span, statement) => {
+
+                let outer = self.span.replace(*span);
+                self.statement(statement);
+                self.span = outer;
+            }
+
+            Statement::Assignment(VariableType::Singular(name), expr) => {
+
+                self.expression(expr);
+                let slot = self.slot(name);
+                self.emit(Instruction::Store(slot));
+            }
+
+            Statement::Assignment(VariableType::Nested(name, accessors), expr) => {
+
+                self.expression(expr);
+                let slot = self.slot(name);
+                let keys = self.path(accessors);
+                self.emit(Instruction::StoreNested(slot, keys));
+            }
+
+            Statement::BareExpression(expr) => {
+
+                self.expression(expr);
+                self.emit(Instruction::Echo);
+            }
+
+            Statement::Yield(expr) => {
+
+                self.expression(expr);
+                self.emit(Instruction::Yield);
+            }
+
+            Statement::ScopedStatementBlock(statements) => {
+
+                self.begin_scope();
+
+                for statement in statements {
+                    self.statement(statement);
+                }
+
+                self.end_scope();
+            }
+
+            Statement::While(condition, body) => {
+
+                self.emit(Instruction::Mark);
+                self.marks += 1;
+
+                let start = self.here();
+
+                self.loops.push(Loop {
+                    marks: self.marks,
+                    exits: Vec::new(),
+                    condition: start
+                });
+
+                self.expression(condition);
+                let exit = self.emit(Instruction::JumpIfFalse(0));
+
+                // Each iteration of the body gets a fresh scope, the same way an if block does
+                self.emit(Instruction::EnterScope);
+
+                let mut lost = Vec::new();
+
+                for statement in body {
+                    self.statement(statement);
+
+                    // A yield demolished the scope of the loop so there is nothing left to iterate
+                    if yields(statement) {
+                        lost.push(self.emit(Instruction::CheckScope(0)));
+                    }
+                }
+
+                self.emit(Instruction::Restore);
+                self.emit(Instruction::Jump(start));
+
+                let finished = match self.loops.pop() {
+                    Some(finished) => { finished }
+                    None => { return; }
+                };
+
+                let end = self.here();
+                self.patch(exit, end);
+                self.patch_all(lost, end);
+                self.patch_all(finished.exits, end);
+
+                self.emit(Instruction::Unmark(1));
+                self.marks -= 1;
+            }
+
+            Statement::For(variables, iterable, body) => {
+
+                match iterable {
+                    Iterable::Expression(expression) => {
+                        self.expression(expression);
+                        self.emit(Instruction::Iterate(variables.len() == 2));
+                    }
+                    Iterable::Range(start, end, inclusive) => {
+                        self.expression(start);
+                        self.expression(end);
+                        self.emit(Instruction::Range(*inclusive));
+                    }
+                }
+
+                // The iteration stays below the mark so leaving the loop early keeps it around
+                self.emit(Instruction::Mark);
+                self.marks += 1;
+
+                let start = self.here();
+
+                self.loops.push(Loop {
+                    marks: self.marks,
+                    exits: Vec::new(),
+                    condition: start
+                });
+
+                let exit = self.emit(Instruction::Next(0));
+
+                self.emit(Instruction::EnterScope);
+
+                if variables.len() == 2 {
+                    self.emit(Instruction::Unpack);
+                }
+
+                // The value is on top of the key, so the variables are stored in reverse
+                for variable in variables.iter().rev() {
+                    let slot = self.slot(variable);
+                    self.emit(Instruction::Store(slot));
+                }
+
+                let mut lost = Vec::new();
+
+                for statement in body {
+                    self.statement(statement);
+
+                    if yields(statement) {
+                        lost.push(self.emit(Instruction::CheckScope(0)));
+                    }
+                }
+
+                self.emit(Instruction::Restore);
+                self.emit(Instruction::Jump(start));
+
+                let finished = match self.loops.pop() {
+                    Some(finished) => { finished }
+                    None => { return; }
+                };
+
+                let end = self.here();
+                self.patch(exit, end);
+                self.patch_all(lost, end);
+                self.patch_all(finished.exits, end);
+
+                self.emit(Instruction::Unmark(1));
+                self.emit(Instruction::Pop);
+                self.marks -= 1;
+            }
+
+            Statement::Break => {
+
+                let (marks, _) = match self.loops.last() {
+                    Some(current) => { (current.marks, current.condition) }
+                    None => {
+                        self.emit(Instruction::Fail(|| ExecutionError::InvalidOperation("'break' used outside of a loop")));
+                        return;
+                    }
+                };
+
+                self.leave_to(marks);
+                let exit = self.emit(Instruction::Jump(0));
+
+                if let Some(current) = self.loops.last_mut() {
+                    current.exits.push(exit);
+                }
+            }
+
+            Statement::Continue => {
+
+                let (marks, condition) = match self.loops.last() {
+                    Some(current) => { (current.marks, current.condition) }
+                    None => {
+                        self.emit(Instruction::Fail(|| ExecutionError::InvalidOperation("'continue' used outside of a loop")));
+                        return;
+                    }
+                };
+
+                self.leave_to(marks);
+                self.emit(Instruction::Jump(condition));
+            }
+
+            Statement::FunctionDefinition(name, params, body) => {
+
+                let compiled = Compiler::compile_function(&mut *self.symbols, params, body);
+
+                self.chunk.functions.push(Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: Rc::new(body.clone()),
+                    compiled: Some(Rc::new(compiled))
+                });
+
+                let function = self.chunk.functions.len() - 1;
+                self.emit(Instruction::MakeFunction(function));
+
+                let slot = self.slot(name);
+                self.emit(Instruction::Store(slot));
+            }
+
+            Statement::Return(expr) => {
+
+                if self.locals.is_none() {
+                    self.emit(Instruction::Fail(|| ExecutionError::InvalidOperation("'return' used outside of a function")));
+                    return;
+                }
+
+                self.expression(expr);
+                self.emit(Instruction::Return);
+            }
+        }
+    }
+
+    /// Start a new scope that ends with end_scope
+    fn begin_scope(&mut self) {
+        self.emit(Instruction::Mark);
+        self.emit(Instruction::EnterScope);
+        self.marks += 1;
+    }
+
+    /// Throw away the scope started by begin_scope
+    fn end_scope(&mut self) {
+        self.emit(Instruction::Restore);
+        self.emit(Instruction::Unmark(1));
+        self.marks -= 1;
+    }
+
+    /// Drop the marks of any scopes inside of a loop and restore the loop's own
+    fn leave_to(&mut self, marks: usize) {
+
+        if self.marks > marks {
+            self.emit(Instruction::Unmark(self.marks - marks));
+        }
+        self.emit(Instruction::Restore);
+    }
+
+    /// Compile an expression, leaving its result on the stack
+    fn expression(&mut self, expression: &Expr) {
+
+        match expression {
+
+            Expr::Located(span, expression) => {
+
+                let outer = self.span.replace(*span);
+                self.expression(expression);
+                self.span = outer;
+            }
+
+            Expr::Number(i) => {
+                let constant = self.constant(RecordData::Integer(i.clone()));
+                self.emit(Instruction::Constant(constant));
+            }
+
+            // The precision can change while running, so floats are read in when they are used
+            Expr::Real(digits) => {
+                let digits = self.digits(digits);
+                self.emit(Instruction::Real(digits));
+            }
+
+            Expr::Rational(r) => {
+                let constant = self.constant(RecordData::Rational(r.clone()));
+                self.emit(Instruction::Constant(constant));
+            }
+
+            Expr::Imaginary(digits) => {
+                let digits = self.digits(digits);
+                self.emit(Instruction::Imaginary(digits));
+            }
+
+            Expr::Bool(b) => {
+                let constant = self.constant(RecordData::Bool(*b));
+                self.emit(Instruction::Constant(constant));
+            }
+
+            Expr::Nil => {
+                let constant = self.constant(RecordData::Nil);
+                self.emit(Instruction::Constant(constant));
+            }
+
+            Expr::String(s) => {
+                let constant = self.constant(RecordData::String(s.clone()));
+                self.emit(Instruction::Constant(constant));
+            }
+
+            // Each part is left on the stack as a string before they are all joined
+            Expr::Format(parts) => {
+
+                for part in parts {
+                    match part {
+                        FormatPart::Text(text) => {
+                            let constant = self.constant(RecordData::String(text.clone()));
+                            self.emit(Instruction::Constant(constant));
+                        }
+                        FormatPart::Value(expression, spec) => {
+                            self.expression(expression);
+                            self.chunk.formats.push(spec.clone());
+                            self.emit(Instruction::Format(self.chunk.formats.len() - 1));
+                        }
+                    }
+                }
+                self.emit(Instruction::Concat(parts.len()));
+            }
+
+            Expr::Variable(VariableType::Singular(name)) => {
+                let slot = self.slot(name);
+                self.emit(Instruction::Load(slot));
+            }
+
+            Expr::Variable(VariableType::Nested(name, accessors)) => {
+                let slot = self.slot(name);
+                let keys = self.path(accessors);
+                self.emit(Instruction::LoadNested(slot, keys));
+            }
+
+            Expr::Dict(entries) => {
+
+                // Each key is worked out before its value
+                for entry in entries {
+                    self.expression(&entry.key);
+                    self.expression(&entry.value);
+                }
+                self.emit(Instruction::MakeDict(entries.len()));
+            }
+
+            Expr::List(items) => {
+
+                for item in items {
+                    self.expression(item);
+                }
+                self.emit(Instruction::MakeList(items.len()));
+            }
+
+            Expr::Call(name, params) => {
+
+                let slot = self.slot(name);
+                self.emit(Instruction::Load(slot));
+
+                for param in params {
+                    self.expression(param);
+                }
+                self.emit(Instruction::Call(params.len()));
+            }
+
+            Expr::BuiltInCall(name, params) => {
+
+                // Drop works on the variable itself, not its value
+                if name == "drop" {
+
+                    let variable = match params.as_slice() {
+                        [param] => {
+                            match param.unlocated() {
+                                Expr::Variable(VariableType::Singular(variable)) => { Some(variable) }
+                                _ => { None }
+                            }
+                        }
+                        _ => { None }
+                    };
+
+                    match variable {
+                        Some(variable) => {
+                            let slot = self.slot(variable);
+                            self.emit(Instruction::Drop(slot));
+                        }
+                        None => {
+                            self.emit(Instruction::Fail(|| ExecutionError::InvalidParameters));
+                        }
+                    }
+                    return;
+                }
+
+                for param in params {
+                    self.expression(param);
+                }
+
+                let name = self.name(name);
+                self.emit(Instruction::BuiltIn(name, params.len()));
+            }
+
+            Expr::Access(item, Accessors::Dot, method) => {
+
+                self.expression(item);
+
+                for param in method.params.iter() {
+                    self.expression(param);
+                }
+
+                let name = self.name(&method.method);
+                self.emit(Instruction::Method(name, method.params.len()));
+            }
+
+            Expr::UnaryOp(expression, op) => {
+
+                self.expression(expression);
+                self.emit(Instruction::Unary(*op));
+            }
+
+            Expr::Op(lhs, op @ (Opcode::And | Opcode::Or), rhs) => {
+
+                self.logic(lhs, *op, rhs);
+            }
+
+            Expr::Op(lhs, op, rhs) => {
+
+                self.expression(lhs);
+                self.expression(rhs);
+                self.emit(Instruction::Binary(*op));
+            }
+
+            Expr::IfExpression(conditional_blocks) => {
+
+                self.if_expression(conditional_blocks);
+            }
+        }
+    }
+
+    /// Compile a logic operation. The right hand side is skipped when the left hand side 
+    /// already decides the result, which is false for '&&' and true for '||'
+    fn logic(&mut self, lhs: &Expr, op: Opcode, rhs: &Expr) {
+
+        let decided = op == Opcode::Or;
+
+        let mut jumps = Vec::new();
+
+        for side in [lhs, rhs] {
+            self.expression(side);
+
+            if decided {
+                jumps.push(self.emit(Instruction::JumpIfTrue(0)));
+            } else {
+                jumps.push(self.emit(Instruction::JumpIfFalse(0)));
+            }
+        }
+
+        let undecided = self.constant(RecordData::Bool(!decided));
+        self.emit(Instruction::Constant(undecided));
+        let end = self.emit(Instruction::Jump(0));
+
+        let decision = self.here();
+        self.patch_all(jumps, decision);
+
+        let result = self.constant(RecordData::Bool(decided));
+        self.emit(Instruction::Constant(result));
+
+        let after = self.here();
+        self.patch(end, after);
+    }
+
+    /// Compile an if expression. Only one block is ever executed, and the expression
+    /// results in whatever that block yields
+    fn if_expression(&mut self, conditional_blocks: &[ConditionalBlock]) {
+
+        let mut ends = Vec::new();
+        let mut lost = Vec::new();
+
+        for conditional in conditional_blocks {
+
+            let skip = match &conditional.expression {
+                Some(expression) => {
+                    self.expression(expression);
+                    Some(self.emit(Instruction::JumpIfFalse(0)))
+                }
+                None => { None }
+            };
+
+            self.begin_scope();
+
+            for statement in conditional.body.iter() {
+                self.statement(statement);
+
+                // If one of the statements caused us to leave the current scope then we need to stop!
+                if yields(statement) {
+                    lost.push(self.emit(Instruction::CheckScope(0)));
+                }
+            }
+
+            self.end_scope();
+            self.emit(Instruction::Nothing);
+            ends.push(self.emit(Instruction::Jump(0)));
+
+            if let Some(skip) = skip {
+                let next = self.here();
+                self.patch(skip, next);
+            }
+        }
+
+        // No block was executed
+        self.emit(Instruction::Nothing);
+
+        if !lost.is_empty() {
+            ends.push(self.emit(Instruction::Jump(0)));
+
+            let yielded = self.here();
+            self.patch_all(lost, yielded);
+
+            self.emit(Instruction::Unmark(1));
+            self.emit(Instruction::Yielded);
+        }
+
+        let end = self.here();
+        self.patch_all(ends, end);
+    }
+}
+
+/// Check if a statement can yield out of the scope it is run in
+fn yields(statement: &Statement) -> bool {
+
+    match statement {
+        Statement::Located(_, statement) => { yields(statement) }
+        Statement::Yield(_) => { true }
+        Statement::ScopedStatementBlock(statements) => { statements.iter().any(|statement| yields(statement)) }
+        _ => { false }
+    }
+}
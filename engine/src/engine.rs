@@ -7,36 +7,82 @@
 */
 
 
-use std::convert::TryFrom;
-use rug::{Integer, Float, Assign, ops::Pow};
 use std::{ cell::RefCell, rc::Rc };
+use std::collections::HashMap;
 
 extern crate micron_ast;
 use micron_ast::{ 
     Statement, 
     Expr, 
     VariableType, 
-    DictAccessType, 
     ConditionalBlock,
+    Iterable,
     Accessors, 
     MemberMethod, 
     UnaryOpcode, 
     Opcode, 
-    RADIX, 
+    Span,
+    FormatPart,
     FLOAT_PRECISION
 };
 
-use crate::types::{ Dictionary, RecordData };
-use crate::error::ExecutionError;
-
+use crate::types::{ Dictionary, List, RecordData, Function };
+use crate::error::{ ExecutionError, EvalError };
+use crate::value::{ Value, Key };
+use crate::host::{ self, HostFunction, NativeFunction };
+use crate::ops;
+use crate::math;
+
+
+/// Change in control flow requested by a statement that has yet to be handled 
+/// by the loop or function it applies to
+#[derive(Debug, Clone, PartialEq)]
+enum FlowControl {
+    None,
+    Break,
+    Continue,
+    Return
+}
 
 /// The Micron Engine 
 #[derive(Debug, Clone)]
 pub struct Engine {
 
     /// Stored data
-    scopes: Vec<Dictionary>,
-    op_stack: Vec<Rc<RefCell<RecordData>>>
+    scopes: Vec<Dictionary<String>>,
+    op_stack: Vec<Rc<RefCell<RecordData>>>,
+
+    /// Loop and function call state
+    loop_depth: usize,
+    flow_control: FlowControl,
+    return_value: Option<RecordData>,
+
+    /// Index of the first scope that belongs to the function currently being called.
+    /// Variables in scopes below this (other than the global scope) are hidden
+    frame_base: usize,
+    call_depth: usize,
+
+    /// Print the result of bare expressions as they are executed
+    echo: bool,
+    last_value: Option<Rc<RefCell<RecordData>>>,
+
+    /// Precision, in bits, that floats are read in and promoted at
+    precision: u32,
+
+    /// Create missing dictionaries along the way when assigning to a nested variable
+    auto_vivify: bool,
+
+    /// Location of the statement or expression that caused the last error
+    error_location: Option<Span>,
+
+    /// Functions registered by the host application
+    host_functions: HashMap<String, NativeFunction>
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
@@ -45,8 +91,138 @@ impl Engine {
     pub fn new() -> Self {
         Self {
             scopes: Vec::new(),
-            op_stack: Vec::new()
+            op_stack: Vec::new(),
+            loop_depth: 0,
+            flow_control: FlowControl::None,
+            return_value: None,
+            frame_base: 0,
+            call_depth: 0,
+            echo: false,
+            last_value: None,
+            precision: FLOAT_PRECISION,
+            auto_vivify: false,
+            error_location: None,
+            host_functions: HashMap::new()
+        }
+    }
+
+    /// Register a native function that scripts can call with '#name(...)'. The arguments given
+    /// by the script are converted to the types the function takes, and anything the function
+    /// returns is handed back to the script. Names that belong to a built in function are refused
+    /// 
+    /// ```
+    /// # use micron_engine::{ Engine, Value };
+    /// let mut engine = Engine::new();
+    /// engine.register_fn("add", |a: i64, b: i64| a + b).unwrap();
+    /// assert_eq!(engine.eval("#add(2, 3) * 2").unwrap(), Some(Value::from(10)));
+    /// ```
+    pub fn register_fn<Args, F: HostFunction<Args>>(&mut self, name: &str, function: F) -> Result<(), ExecutionError> {
+
+        if host::is_built_in(name) {
+            return Err(ExecutionError::ReservedFunctionName(name.to_string()));
+        }
+
+        self.host_functions.insert(name.to_string(), function.into_native());
+        Ok(())
+    }
+
+    /// Set if the result of bare expressions should be printed to stdout as they are executed.
+    /// This is off unless the host turns it on
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Set the precision, in bits, that floats are read in and promoted at from here on
+    pub fn set_precision(&mut self, bits: u32) -> Result<(), ExecutionError> {
+        self.precision = ops::precision_bits(&RecordData::Integer(bits.into()))?;
+        Ok(())
+    }
+
+    /// Precision, in bits, that floats are read in and promoted at
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// Set if assigning to a nested variable creates the dictionaries that are missing along the
+    /// way, so 'a['x']['y'] = 1' works even when 'a' has no key 'x' yet
+    pub fn set_auto_vivify(&mut self, enabled: bool) {
+        self.auto_vivify = enabled;
+    }
+
+    /// Check if assigning to a nested variable creates the dictionaries that are missing along the way
+    pub fn auto_vivify(&self) -> bool {
+        self.auto_vivify
+    }
+
+    /// Parse and execute source code. If the final statement is a bare expression
+    /// its value is handed back instead of being echoed
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, EvalError> {
+
+        let source = micron_parser::strip_comments(source);
+
+        let mut statements = match micron_parser::micron::ProgramParser::new().parse(&source) {
+            Ok(statements) => { statements }
+            Err(e) => { return Err(EvalError::ParseError(e.to_string())); }
+        };
+
+        let last_statement = match statements.pop() {
+            Some(statement) => { *statement }
+            None => { return Ok(None); }
+        };
+
+        for statement in statements {
+            if let Some(e) = self.execute_statement(*statement) {
+                return Err(EvalError::ExecutionError(e));
+            }
+        }
+
+        let is_bare_expression = matches!(last_statement.unlocated(), Statement::BareExpression(_));
+
+        // The value of a final bare expression is handed back rather than echoed
+        let echo = self.echo;
+
+        if is_bare_expression {
+            self.echo = false;
+            self.last_value = None;
+        }
+
+        let result = self.execute_statement(last_statement);
+
+        self.echo = echo;
+
+        if let Some(e) = result {
+            return Err(EvalError::ExecutionError(e));
+        }
+
+        match (is_bare_expression, self.last_value.take()) {
+            (true, Some(value)) => { Ok(Some(Value::from_record(&value.borrow()))) }
+            _ => { Ok(None) }
+        }
+    }
+
+    /// Get a copy of a variable from the global scope
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+
+        match self.scopes.first() {
+            Some(scope) => { scope.get(&name.to_string()).map(|record| Value::from_record(&record.borrow())) }
+            None => { None }
+        }
+    }
+
+    /// Set a variable in the global scope, creating it if it doesn't exist
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), ExecutionError> {
+
+        let record = match value.into_record() {
+            Some(record) => { record }
+            None => { return Err(ExecutionError::InvalidOperation("Functions can not be handed to the engine")); }
+        };
+
+        if self.scopes.is_empty() {
+            self.new_scope();
         }
+
+        self.scopes[0].set(&name.to_string(), record);
+        Ok(())
     }
 
     /// Add a new scope to the scope list
@@ -63,7 +239,7 @@ impl Engine {
 
         // Remove any still existing scopes from operation
         'scope_resize: loop {
-            if scope_size == self.scopes.len() || self.scopes.len() == 0 {
+            if scope_size == self.scopes.len() || self.scopes.is_empty() {
                 break 'scope_resize;
             } else {
                 self.pop_scope();
@@ -74,7 +250,21 @@ impl Engine {
     /// Get a record - Traverses scopes in reverse in an attempt
     /// to find the requested record. The first one found will be returned
     fn get_record(&self, key: &String) -> Option<Rc<RefCell<RecordData>>> {
-        for scope in self.scopes.iter().rev() {
+
+        if let Some(record) = self.get_local_record(key) {
+            return Some(record);
+        }
+
+        // Functions can still see the global scope
+        if self.frame_base > 0 {
+            return self.scopes[0].get(key);
+        }
+        None
+    }
+
+    /// Get a record from the scopes of the current function (or all scopes outside of a function)
+    fn get_local_record(&self, key: &String) -> Option<Rc<RefCell<RecordData>>> {
+        for scope in self.scopes[self.frame_base..].iter().rev() {
             match scope.get(key) {
                 Some(record) => { return Some(record); }
                 None         => { /* Continue along  */ }
@@ -84,10 +274,10 @@ impl Engine {
     }
 
     /// Get the current scope
-    fn current_scope (&mut self) -> &mut Dictionary {
+    fn current_scope (&mut self) -> &mut Dictionary<String> {
 
         // If there is no scope for some reason
-        if self.scopes.len() == 0 {
+        if self.scopes.is_empty() {
 
             // add  a new scope
             self.new_scope();
@@ -106,9 +296,10 @@ impl Engine {
             When setting a record we attempt to get the record first. This is to ensure we are setting
             the correct variable. If the variable exists in an outer scope it will take priority over
             creating a new variable in the local scope. If it was created in the local scope, this will
-            update the one in the local scope
+            update the one in the local scope. Within a function only the function's own scopes are
+            considered so a call can never overwrite the variables of its caller
         */
-        match self.get_record(key) {
+        match self.get_local_record(key) {
             Some(existing_record) => {
 
                 existing_record.borrow_mut().update_value(record);
@@ -127,19 +318,19 @@ impl Engine {
     /// Attempt to remove a variable
     fn rm_record(&mut self, key: &String) -> Option<ExecutionError> {
         match self.current_scope().remove(key) {
-            true => return None,
-            false => return Some(ExecutionError::UnknownVariable)
+            true => None,
+            false => Some(ExecutionError::UnknownVariable)
         }
     }
 
     /// Get a record from the operational dictionary if it exists by the Variable Type (Singular v.s Nested)
     /// This will return an editable value
-    fn get_record_by_var_type(&self, var_type: VariableType) -> Option<Rc<RefCell<RecordData>>> {
+    fn get_record_by_var_type(&mut self, var_type: VariableType) -> Result<Rc<RefCell<RecordData>>, ExecutionError> {
 
         match var_type {
             VariableType::Singular(var_name) => {
 
-                return self.get_record(&var_name);
+                self.get_record(&var_name).ok_or(ExecutionError::UnknownVariable)
             }
 
             //  This will drill into the any n-dictionaries and I'm very proud of it
@@ -151,87 +342,66 @@ impl Engine {
                     Some(existing_variable) => {
                         Box::new( existing_variable)
                     }
-                    None => { return None; }
+                    None => { return Err(ExecutionError::UnknownVariable); }
                 };
 
-                // For every item in the accessor list we drill into the dictionaries
-                for item in accessor.iter() {
-
-                    let top_level_val = top_level_variable.borrow().get_value();
+                // For every item in the accessor list we drill into the dictionaries and lists. 
+                // Keys are expressions, so they are worked out as they are reached
+                for item in accessor {
 
-                    // Ensure the current item is a dictionary
-                    match top_level_val {
-                        RecordData::Dict(dictionary) => {
+                    let key = self.evaluate(*item)?;
 
-                            // See what the accessor type is
-                            match item {
-
-                                // If its a raw string then we just have to get its item
-                                DictAccessType::RawValue(string_key) => {
-        
-                                    // Set the top level variable to its inner item
-                                    let new_value = match dictionary.get(&string_key) {
-                                        Some(val) => { val }
-                                        None => { 
-                                            eprintln!("Unable to find record for key '{}'", string_key);
-                                            return None 
-                                        }
-                                    };
-
-                                    top_level_variable = Box::new(new_value)
-                                }
-            
-                                // If its a variable we have to load the variable and ensure its a string first
-                                // once thats done we can update the item 
-                                DictAccessType::Variable(var_key) => {
-
-                                    let suspected_string_var = match self.get_record(&var_key) {
-                                        Some(val) => { val }
-                                        None => { 
-                                            eprintln!("Could not find string key '{}'", var_key);
-                                            return None 
-                                        }
-                                    };
-
-                                    let suspect_value = suspected_string_var.borrow().get_value();
-
-                                    match suspect_value {
-                                        RecordData::String(string_key) => {
-
-                                            // Set the top level variable to its inner item
-                                            let new_value = match dictionary.get(&string_key) {
-                                                Some(val) => { val }
-                                                None => { 
-                                                    eprintln!("Unable to find record for key '{}'", string_key);
-                                                    return None 
-                                                }
-                                            };
-
-                                            top_level_variable = Box::new(new_value)
-                                        }
-
-                                        _ => {
-
-                                            eprintln!("Variable for dictionary key is not a string!");
-                                            return None;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        _ => {
-                            return None;
-                        }
-                    }
+                    // Set the top level variable to its inner item
+                    *top_level_variable = ops::element(&top_level_variable, &key)?;
                 }
-                return Some(*top_level_variable);
+                Ok(*top_level_variable)
             }
         }
     }
 
+    /// Assign a value to a nested variable. A key missing from the last dictionary is created, and 
+    /// so are missing dictionaries along the way when auto vivify is on
+    #[allow(clippy::vec_box)]
+    fn assign_nested(&mut self, var_name: String, accessor: Vec<Box<Expr>>, value: RecordData) -> Result<(), ExecutionError> {
+
+        let variable = self.get_record(&var_name).ok_or(ExecutionError::UnknownVariable)?;
+
+        let mut keys = Vec::new();
+
+        for item in accessor {
+            keys.push(self.evaluate(*item)?);
+        }
+
+        ops::assign(variable, &keys, value, self.auto_vivify)
+    }
+
     /// Execute an AST statement
     pub fn execute_statement(&mut self, statement: Statement) -> Option<ExecutionError> {
 
+        self.error_location = None;
+        
+        self.process_statement(statement)
+    }
+
+    /// Location in the source of the statement or expression that caused the most recent 
+    /// error, if the AST it came from was given locations by the parser
+    pub fn error_location(&self) -> Option<Span> {
+        self.error_location
+    }
+
+    /// Record the location of an error. The innermost location is the first to see the 
+    /// error so it is the one that gets kept
+    fn locate_error(&mut self, span: Span, error: Option<ExecutionError>) -> Option<ExecutionError> {
+
+        if error.is_some() && self.error_location.is_none() {
+            self.error_location = Some(span);
+        }
+        error
+    }
+
+    /// Process a statement
+    fn process_statement(&mut self, statement: Statement) -> Option<ExecutionError> {
+
         /*
 
             Match the statement and do what it asks
@@ -239,6 +409,12 @@ impl Engine {
         */
         match statement {
 
+            Statement::Located(span, statement) => {
+
+                let result = self.process_statement(*statement);
+                return self.locate_error(span, result);
+            }
+
             Statement::Assignment(var_type, expr) => {
 
                 // Clear operational stack just in case
@@ -255,21 +431,20 @@ impl Engine {
 
                             None => {
 
+                                // An expression that results in nothing, like a call to a function 
+                                // without a return, assigns nil
                                 let value = match self.op_stack.pop() {
-                                    None => {
-                                        return Some(ExecutionError::StackError);
-                                    }
-
-                                    Some(val) => { val }
+                                    None => { RecordData::Nil }
+                                    Some(val) => { val.borrow().clone() }
                                 };
 
-                                self.set_record(&var_name, value.borrow().clone());
+                                self.set_record(&var_name, value);
                             }
                         }
                     }
 
                     // Assign a more complicated 'nested' variable i.e  a['key_1']['key_2'] = "Some value"
-                    VariableType::Nested(_, _) => {
+                    VariableType::Nested(var_name, accessor) => {
 
                         match self.execute_expression(*expr) {
 
@@ -277,25 +452,14 @@ impl Engine {
 
                             None => {
 
-                                match self.op_stack.pop() {
-                                    None => {
-                                        return Some(ExecutionError::StackError);
-                                    }
-
-                                    Some(val) => { 
-
-                                        // Nested variables are expected to exist already
-                                        match self.get_record_by_var_type(var_type) {
-
-                                            Some(variable) => {
-                                                let mut lhs = variable.borrow_mut();
-                                                lhs.update_value(val.borrow().clone());
-                                            }
-                                            None => {
-                                                return Some(ExecutionError::UnknownVariable);
-                                            }
-                                        }
-                                     }
+                                let value = match self.op_stack.pop() {
+                                    None => { RecordData::Nil }
+                                    Some(val) => { val.borrow().clone() }
+                                };
+
+                                // The variable has to exist, but the key being assigned doesn't
+                                if let Err(e) = self.assign_nested(var_name, accessor, value) {
+                                    return Some(e);
                                 }
                             }
                         }
@@ -312,19 +476,17 @@ impl Engine {
 
                     None => {
 
-                        // Get the resulting expression
-                        match self.op_stack.pop() {
-
-                            // If nothing was placed on the stack thats fine
-                            None => { }
+                        // Get the resulting expression. If nothing was placed on the stack thats fine
+                        let value = self.op_stack.pop();
 
-                            // If there is something on the stack, show it
-                            Some(val) => { 
+                        // If there is something on the stack, show it
+                        if let Some(val) = value {
 
-                                // Print the value for now
-                                println!("{:?}", val.borrow().get_value());
-                             }
-                        };
+                            if self.echo {
+                                println!("{:?}", Value::from_record(&val.borrow()));
+                            }
+                            self.last_value = Some(val);
+                        }
 
                     }
                 }
@@ -362,17 +524,70 @@ impl Engine {
                 self.new_scope();
 
                 for statement in statements {
-                    match self.execute_statement(*statement) {
-                        Some(e) => { 
-                            
-                            self.return_to_scope(scope_size);
-                            return Some(e) 
-                        }
-                        None => {}
+                    if let Some(e) = self.process_statement(*statement) {
+                        self.return_to_scope(scope_size);
+                        return Some(e);
+                    }
+
+                    // A break, continue or return leaves the block for the loop or function to handle
+                    if self.flow_control != FlowControl::None {
+                        break;
                     }
                 }
                 self.return_to_scope(scope_size);
             }
+
+            Statement::While(condition, body) => {
+
+                return self.process_while_loop(*condition, body);
+            }
+
+            Statement::For(variables, iterable, body) => {
+
+                return self.process_for_loop(variables, iterable, body);
+            }
+
+            Statement::Break => {
+
+                if self.loop_depth == 0 {
+                    return Some(ExecutionError::InvalidOperation("'break' used outside of a loop"));
+                }
+                self.flow_control = FlowControl::Break;
+            }
+
+            Statement::Continue => {
+
+                if self.loop_depth == 0 {
+                    return Some(ExecutionError::InvalidOperation("'continue' used outside of a loop"));
+                }
+                self.flow_control = FlowControl::Continue;
+            }
+
+            Statement::FunctionDefinition(name, params, body) => {
+
+                self.set_record(&name.clone(), RecordData::Function(Function { name, params, body: Rc::new(body), compiled: None }));
+            }
+
+            Statement::Return(expr) => {
+
+                if self.call_depth == 0 {
+                    return Some(ExecutionError::InvalidOperation("'return' used outside of a function"));
+                }
+
+                if let Some(e) = self.execute_expression(*expr) {
+                    return Some(e);
+                }
+
+                let value = match self.op_stack.pop() {
+                    None => {
+                        return Some(ExecutionError::StackError);
+                    }
+                    Some(val) => { val.borrow().get_value() }
+                };
+
+                self.return_value = Some(value);
+                self.flow_control = FlowControl::Return;
+            }
         }
 
         None
@@ -383,25 +598,90 @@ impl Engine {
 
         match expression {
 
+            Expr::Located(span, expression) => {
+
+                let result = self.execute_expression(*expression);
+                self.locate_error(span, result)
+            }
+
             // Load a raw integer
             //
             Expr::Number(i) => {
                 self.op_stack.push(Rc::new(RefCell::new(RecordData::Integer(i))));
-                return None;
+                None
             }
 
             // Load a raw real
             //
-            Expr::Real(f) => {
-                self.op_stack.push(Rc::new(RefCell::new(RecordData::Float(f))));
-                return None;
+            Expr::Real(digits) => {
+                match ops::parse_float(&digits, self.precision) {
+                    Ok(f) => { self.op_stack.push(Rc::new(RefCell::new(RecordData::Float(f)))); }
+                    Err(e) => { return Some(e); }
+                }
+                None
+            }
+
+            // Load a raw rational
+            //
+            Expr::Rational(r) => {
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::Rational(r))));
+                None
+            }
+
+            // Load a raw imaginary number
+            //
+            Expr::Imaginary(digits) => {
+                match ops::parse_imaginary(&digits, self.precision) {
+                    Ok(c) => { self.op_stack.push(Rc::new(RefCell::new(RecordData::Complex(c)))); }
+                    Err(e) => { return Some(e); }
+                }
+                None
+            }
+
+            // Load a raw bool
+            //
+            Expr::Bool(b) => {
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::Bool(b))));
+                None
+            }
+
+            // Load nil
+            //
+            Expr::Nil => {
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::Nil)));
+                None
             }
 
             // Load a raw string
             //
             Expr::String(s) => {
                 self.op_stack.push(Rc::new(RefCell::new(RecordData::String(s))));
-                return None;
+                None
+            }
+
+            // Build an interpolated string out of its text and the values placed within it
+            //
+            Expr::Format(parts) => {
+
+                let mut result = String::new();
+
+                for part in parts {
+                    match part {
+                        FormatPart::Text(text) => { result.push_str(&text); }
+                        FormatPart::Value(expression, spec) => {
+
+                            let formatted = self.evaluate(*expression).and_then(|value| ops::format(&value, &spec));
+
+                            match formatted {
+                                Ok(formatted) => { result.push_str(&formatted); }
+                                Err(e) => { return Some(e); }
+                            }
+                        }
+                    }
+                }
+
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::String(result))));
+                None
             }
 
             // Load a variable
@@ -410,20 +690,20 @@ impl Engine {
 
                 // Get the variable from memory
                 match self.get_record_by_var_type(v) {
-                    Some(var) => {
+                    Ok(var) => {
 
                         // If it exists stack it
                         self.op_stack.push(var);
                     }
-                    None => {
+                    Err(e) => {
                         
                         // Otherwise its an error
-                        return Some(ExecutionError::UnknownVariable)
+                        return Some(e)
                     }
                 };
 
                 // Get on out!
-                return None;
+                None
             }
 
             //  Load a new dictionary
@@ -432,59 +712,89 @@ impl Engine {
 
                 let mut new_dict = Dictionary::new();
 
+                // Each key is worked out before its value
                 for entry in dict_entries {
-                    match self.execute_expression(*entry.value) {
+
+                    let key = match self.evaluate(*entry.key).and_then(|key| Key::from_record(&key)) {
+                        Ok(key) => { key }
+                        Err(e) => { return Some(e); }
+                    };
+
+                    match self.evaluate(*entry.value) {
+                        Ok(value) => { new_dict.set(&key, value); }
+                        Err(e) => { return Some(e); }
+                    }
+                }
+
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::Dict(new_dict))));
+                None
+            }
+
+            //  Load a new list
+            //
+            Expr::List(items) => {
+
+                let mut new_list = List::new();
+
+                for item in items {
+                    match self.execute_expression(*item) {
                         Some(e) => return Some(e),
                         None => {
                             match self.op_stack.pop() {
                                 None => {
-                                    eprintln!("Unable to get variable from stack to set item");
                                     return Some(ExecutionError::StackError);
                                 }
                                 Some(val) => { 
-                                    new_dict.set(&entry.key, val.borrow().get_value());
+                                    new_list.push(val.borrow().get_value());
                                 }
                             }
                         }
                     }
                 }
 
-                self.op_stack.push(Rc::new(RefCell::new(RecordData::Dict(new_dict))));
-                return None;
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::List(new_list))));
+                None
             }
 
-            // Modify some variable(s) with a built in function
+            // Call a user defined function
             //
-            Expr::BuiltInModifierCall(modifier_function, variables) => {
+            Expr::Call(function_name, params) => {
 
-                return self.process_modifier(modifier_function, variables);
+                self.process_call(function_name, params)
+            }
+
+            // Call a built in function or one registered by the host
+            //
+            Expr::BuiltInCall(function_name, params) => {
+
+                self.process_built_in(function_name, params)
             }
 
             //  Access
             //
             Expr::Access(access_expr, accessor, method) => {
 
-                return self.perform_access(*access_expr, accessor, *method);
+                self.perform_access(*access_expr, accessor, *method)
             }
 
             // Unary operation
             //
             Expr::UnaryOp(op_expr, op) => {
 
-                return self.perform_unary(*op_expr, op);
+                self.perform_unary(*op_expr, op)
             }
 
             // Operation
             //
             Expr::Op(lhs_expr, op, rhs_expr) => {
 
-                return self.perform_opcode(*lhs_expr, *rhs_expr, op);
+                self.perform_opcode(*lhs_expr, *rhs_expr, op)
             }
 
             // If Expression
             //
             Expr::IfExpression(conditional_blocks) => {
-                return self.process_if_expression(*conditional_blocks)
+                self.process_if_expression(*conditional_blocks)
             }
         }
     }
@@ -498,29 +808,18 @@ impl Engine {
                 Some(expression) => {
 
                     // Process the expression to see if we should execute the body
-                    match self.execute_expression(*expression) {
-                        Some(e) => return Some(e),
-                        None => {}
+                    if let Some(e) = self.execute_expression(*expression) {
+                        return Some(e);
                     }
 
                     // Get the value off the stack
                     let value = match self.op_stack.pop() {
-                        None => {
-                            eprintln!("Unable to get variable from stack for conditional expression");
-                            return Some(ExecutionError::StackError);
-                        }
+                        None => { return Some(ExecutionError::StackError); }
                         Some(val) => { val }
                     };
 
-                    // Check that the value matches requirements for 'true'
-                    let check_condition = match value.borrow().clone() {
-                        RecordData::Integer(i) => { i > 0 }
-                        RecordData::Float(f)   => { f > 0.0 }
-                        _ => { false }
-                    };
-
                     // Explicitly continue if the condition wasn't true
-                    if !check_condition {
+                    if !value.borrow().is_truthy() {
                         continue 'condition_loop;
                     }
                 }
@@ -539,7 +838,8 @@ impl Engine {
             for expression in conditional.body {
                 
                 // Execute each statement
-                if let Some(e) =  self.execute_statement(*expression) {
+                if let Some(e) =  self.process_statement(*expression) {
+                    self.return_to_scope(scope_size);
                     return Some(e);
                 };
 
@@ -548,694 +848,539 @@ impl Engine {
                 if self.scopes.len() <= scope_size {
                     break 'condition_loop;
                 }
+
+                // A break, continue or return leaves the block for the loop or function to handle
+                if self.flow_control != FlowControl::None {
+                    break;
+                }
             }
 
             self.return_to_scope(scope_size);
+
+            // Only one block of the conditional is ever executed
+            break 'condition_loop;
         }
 
         None
     }
 
-    /// Process a modification 
-    fn process_modifier(&mut self, modifier_function: String, variable: String) -> Option<ExecutionError> {
+    /// Process a while loop
+    #[allow(clippy::vec_box)]
+    fn process_while_loop(&mut self, condition: Expr, body: Vec<Box<Statement>>) -> Option<ExecutionError> {
 
-        match modifier_function.as_str() {
+        let scope_size = self.scopes.len();
 
-            "drop" => {
+        self.loop_depth += 1;
 
-                match self.rm_record(&variable) {
-                    Some(e) => return Some(e),
-                    None    => {
-                        self.op_stack.push(Rc::new(RefCell::new(
-                            RecordData::Integer(Integer::from(1))
-                        )));
-                        None
-                    }
+        'while_loop: loop {
+
+            // Process the condition to see if we should execute the body
+            if let Some(e) = self.execute_expression(condition.clone()) {
+                self.loop_depth -= 1;
+                return Some(e);
+            }
+
+            let value = match self.op_stack.pop() {
+                None => {
+                    self.loop_depth -= 1;
+                    return Some(ExecutionError::StackError);
                 }
+                Some(val) => { val }
+            };
+
+            if !value.borrow().is_truthy() {
+                break 'while_loop;
             }
 
-            _ => {
-                return Some(ExecutionError::UnknownBuiltInFunction(modifier_function));
+            match self.process_loop_body(&body, scope_size, Vec::new()) {
+                Ok(true)  => { }
+                Ok(false) => { break 'while_loop; }
+                Err(e) => {
+                    self.loop_depth -= 1;
+                    return Some(e);
+                }
             }
         }
-    }
 
-    /// Perform actions on an accessor
-    fn perform_access(&mut self, item_expr: Expr, accessor: Accessors, method: MemberMethod) -> Option<ExecutionError> {
+        self.loop_depth -= 1;
+        None
+    }
 
-        // Load the variable to access
-        if let Some(err) = self.execute_expression(item_expr) {
-            return Some(err);
-        }
+    /// Process a for loop, running the body once for every item of the iterable
+    #[allow(clippy::vec_box)]
+    fn process_for_loop(&mut self, variables: Vec<String>, iterable: Iterable, body: Vec<Box<Statement>>) -> Option<ExecutionError> {
 
-        // Get the item
-        let accessed_item = match self.op_stack.pop() {
-            None => {
-                return Some(ExecutionError::StackError);
+        let iteration = match iterable {
+            Iterable::Expression(expression) => {
+                self.evaluate(*expression).and_then(|item| ops::iterate(item, variables.len() == 2))
+            }
+            Iterable::Range(start, end, inclusive) => {
+                self.evaluate_method_params(vec![start, end], 2).and_then(|mut bounds| {
+                    let end = bounds.remove(1);
+                    ops::range(bounds.remove(0), end, inclusive)
+                })
             }
+        };
 
-            Some(val) => { 
-                val
-             }
+        let iteration = match iteration {
+            Ok(iteration) => { iteration }
+            Err(e) => { return Some(e); }
         };
 
-        /*
-        
-                TODO : .set_precision()     - Modify the actual data item
-                       .with_precision()    - Make a copy with given precision
-                       .at()                - Copy single string char
-        
-        */
+        let scope_size = self.scopes.len();
 
-        match accessor {
+        self.loop_depth += 1;
 
-            Accessors::Dot => {
+        let mut result = None;
 
-                match method.method.as_str() {
+        for item in iteration {
 
-                    "as_string" => {
-                        let mut new_item = accessed_item.borrow().get_value();
-                        match new_item.to_string() {
-                            Some(v) => { self.op_stack.push(Rc::new(RefCell::new(v.clone()))) }
-                            None    => { return Some(ExecutionError::ConversionFailure(method.method, "Represent item as string".to_string())) }
+            // Two variables take the key and value of each item
+            let bindings = match variables.as_slice() {
+                [key, value] => {
+                    match ops::unpack(item) {
+                        Ok((k, v)) => { vec![(key, k), (value, v)] }
+                        Err(e) => {
+                            result = Some(e);
+                            break;
                         }
-                        None
                     }
+                }
+                _ => { variables.iter().map(|name| (name, item.clone())).collect() }
+            };
+
+            match self.process_loop_body(&body, scope_size, bindings) {
+                Ok(true)  => { }
+                Ok(false) => { break; }
+                Err(e) => {
+                    result = Some(e);
+                    break;
+                }
+            }
+        }
 
-                    "as_int" => {
-                        let mut new_item = accessed_item.borrow().get_value();
-                        match new_item.to_int() {
-                            Some(v) => { self.op_stack.push(Rc::new(RefCell::new(v.clone()))) }
-                            None    => { return Some(ExecutionError::ConversionFailure(method.method, "Represent item as int".to_string())) }
-                        }
-                        None
-                    }
+        self.loop_depth -= 1;
+        result
+    }
 
-                    "as_float" => {
-                        let mut new_item = accessed_item.borrow().get_value();
-                        match new_item.to_float() {
-                            Some(v) => { self.op_stack.push(Rc::new(RefCell::new(v.clone()))) }
-                            None    => { return Some(ExecutionError::ConversionFailure(method.method, "Represent item as float".to_string())) }
-                        }
-                        None
-                    }
+    /// Run the body of a loop once with the given variables set, and report back if the loop should keep going
+    fn process_loop_body(&mut self, body: &[Box<Statement>], scope_size: usize, bindings: Vec<(&String, RecordData)>) -> Result<bool, ExecutionError> {
 
-                    "to_string" => {
-                        let mut new_item = accessed_item.borrow().get_value();
-                        match new_item.to_string() {
-                            Some(v) => { 
-                                accessed_item.borrow_mut().update_value(v.clone());
-                            }
-                            None    => { 
-                                return Some(ExecutionError::ConversionFailure(method.method, "Convert item to string".to_string())) 
-                            }
-                        }
-                        None
-                    }
+        // Each iteration of the body gets a fresh scope, the same way an if block does
+        self.new_scope();
 
-                    "to_int" => {
-                        let mut new_item = accessed_item.borrow().get_value();
-                        match new_item.to_int() {
-                            Some(v) => { 
-                                accessed_item.borrow_mut().update_value(v.clone());
-                            }
-                            None    => { 
-                                return Some(ExecutionError::ConversionFailure(method.method, "Convert item to int".to_string()))
-                             }
-                        }
-                        None
-                    }
+        for (name, value) in bindings {
+            self.set_record(name, value);
+        }
 
-                    "to_float" => {
-                        let mut new_item = accessed_item.borrow().get_value();
-                        match new_item.to_float() {
-                            Some(v) => { 
-                                accessed_item.borrow_mut().update_value(v.clone());
-                            
-                            }
-                            None    => { 
-                                return Some(ExecutionError::ConversionFailure(method.method, "Convert item to float".to_string())) 
-                            }
-                        }
-                        None
-                    }
+        for statement in body.iter() {
 
-                    "at" => {
-
-                        if method.params.len() != 1 { 
-                            return Some(ExecutionError::InvalidParameters)
-                        }
-
-                        let exp = *method.params[0].clone();
+            if let Some(e) = self.process_statement(*statement.clone()) {
+                self.return_to_scope(scope_size);
+                return Err(e);
+            }
 
-                        // Evaluate precision
-                        if let Some(e) = self.execute_expression(exp) {
-                            return Some(e);
-                        }
+            // A yield demolished the scope of the loop so there is nothing left to iterate
+            if self.scopes.len() <= scope_size {
+                return Ok(false);
+            }
 
-                        // Get the lhs operand
-                        let location = match self.op_stack.pop() {
-                            Some(n) => { n }
-                            None => { return Some(ExecutionError::StackError); }
-                        };
+            if self.flow_control != FlowControl::None {
+                break;
+            }
+        }
 
-                        let location = location.borrow().get_value();
+        self.return_to_scope(scope_size);
 
-                        match location {
+        match self.flow_control {
+            FlowControl::Break => {
+                self.flow_control = FlowControl::None;
+                return Ok(false);
+            }
+            FlowControl::Continue => {
+                self.flow_control = FlowControl::None;
+            }
+            FlowControl::Return => {
+                // Leave the loop and let the function handle it
+                return Ok(false);
+            }
+            FlowControl::None => { }
+        }
 
-                            RecordData::Integer(i) => {
+        Ok(true)
+    }
 
-                                match accessed_item.borrow().get_value() {
-                                    RecordData::String(s) => {
+    /// Process a call to a user defined function
+    #[allow(clippy::vec_box)]
+    fn process_call(&mut self, function_name: String, params: Vec<Box<Expr>>) -> Option<ExecutionError> {
 
-                                        if i > s.len() {
-                                            return Some(ExecutionError::IndexError);
-                                        }
+        let function = match self.get_record(&function_name) {
+            Some(record) => { record.borrow().get_value() }
+            None => { return Some(ExecutionError::UnknownVariable); }
+        };
 
-                                        let s_idx = String::from(s.bytes().nth(i.to_i64().unwrap() as usize).unwrap() as char);
+        let function = match function {
+            RecordData::Function(f) => { f }
+            _ => { return Some(ExecutionError::InvalidOperation("Attempted to call a non-function type")); }
+        };
 
-                                        self.op_stack.push(Rc::new(RefCell::new(RecordData::String(s_idx))));
+        if function.params.len() != params.len() {
+            return Some(ExecutionError::InvalidParameters);
+        }
 
-                                        None
-                                    }
+        // Evaluate the arguments in the scope of the caller
+        let mut arguments = Vec::new();
 
-                                    _ => {
-                                        return Some(ExecutionError::InvalidOperation("Non-string type does not contain method '.at(N)' "));
-                                    }
-                                }
-                            }
-                            _ => {
-                                return Some(ExecutionError::InvalidOperation("Parameter expected integer"));
-                            }
-                        }
-                    }
+        for param in params {
 
-                    _ => {
+            if let Some(e) = self.execute_expression(*param) {
+                return Some(e);
+            }
 
-                        return Some(ExecutionError::UnknownVariableMethod(".", method.method));
-                    }
-                }
+            match self.op_stack.pop() {
+                None => { return Some(ExecutionError::StackError); }
+                Some(val) => { arguments.push(val.borrow().get_value()); }
             }
         }
-    }
-
-    /// Perform a unary operation
-    fn perform_unary(&mut self, expression: Expr, op: UnaryOpcode) -> Option<ExecutionError> {
 
-        // Load the variable to access
-        if let Some(err) = self.execute_expression(expression) {
-            return Some(err);
+        if self.call_depth >= ops::MAX_CALL_DEPTH {
+            return Some(ExecutionError::CallDepthExceeded(ops::MAX_CALL_DEPTH));
         }
 
-        // Get the item
-        let accessed_item = match self.op_stack.pop() {
-            None => {
-                return Some(ExecutionError::StackError);
-            }
+        // Make sure the global scope exists before we hide everything above it
+        if self.scopes.is_empty() {
+            self.new_scope();
+        }
 
-            Some(val) => { 
-                val
-             }
-        };
+        let scope_size = self.scopes.len();
+        let stack_size = self.op_stack.len();
 
-        let item_value = accessed_item.borrow().get_value();
+        // Hide the caller's scopes and loops from the function
+        let caller_frame_base = self.frame_base;
+        let caller_loop_depth = self.loop_depth;
 
-        match item_value.clone() {
+        self.frame_base = scope_size;
+        self.loop_depth = 0;
+        self.call_depth += 1;
 
-            RecordData::Integer(v) => {
-                return self.perform_unary_integer(v, op);
-            }
-            
-            RecordData::Float(_) => {
+        self.new_scope();
 
-                match accessed_item.borrow().get_value().to_int() {
-                    Some(v) => {
-                        match v {
-                            RecordData::Integer(i) => {
-                                return self.perform_unary_integer(i, op);
-                            },
-                            _ => {
-                                panic!("RecordData rust function to_int came back okay but somehow didn't return int type");
-                            }
-                        }
-                    }
-                    None    => return Some(ExecutionError::ConversionFailure(
-                        "Converting float to integer".to_string(),
-                        "Required for unary operation".to_string()
-                    ))
-                };
-            }
+        for (name, value) in function.params.iter().zip(arguments) {
+            self.current_scope().set(name, value);
+        }
 
-            RecordData::String(_) => {
-                return Some(ExecutionError::InvalidOperation("Attempted unary operation on string type"));
-            }
+        let mut result = None;
 
+        for statement in function.body.iter() {
 
-            RecordData::Dict(_) => {
-                return Some(ExecutionError::InvalidOperation("Attempted unary operation on dictionary type"));
+            if let Some(e) = self.process_statement((**statement).clone()) {
+                result = Some(Err(e));
+                break;
             }
-        }
-    }
 
-    /// Perform a unary operation
-    fn perform_unary_integer(&mut self, item: Integer, op: UnaryOpcode) -> Option<ExecutionError> {
-
-        match op {
-            UnaryOpcode::BwNot => {
-                self.op_stack.push(Rc::new(RefCell::new(RecordData::Integer( !item ))));
+            // A yield demolished the function's scope, the yielded value is the result
+            if self.scopes.len() <= scope_size {
+                result = self.op_stack.pop().map(|val| Ok(val.borrow().get_value()));
+                break;
             }
 
-            UnaryOpcode::Negate => {
-
-                if item > 0 {
-                    self.op_stack.push(Rc::new(RefCell::new(RecordData::Integer( Integer::from( 0 ) ))));
-                } else {
-                    self.op_stack.push(Rc::new(RefCell::new(RecordData::Integer( Integer::from( 1 ) ))));
-                }
+            if self.flow_control == FlowControl::Return {
+                self.flow_control = FlowControl::None;
+                result = self.return_value.take().map(Ok);
+                break;
             }
         }
-        None
-    }
 
-    /// Perform an opcode
-    pub fn perform_opcode(&mut self, lhs: Expr, rhs: Expr, op: Opcode) -> Option<ExecutionError> {
+        // Restore the caller
+        self.return_to_scope(scope_size);
+        self.op_stack.truncate(stack_size);
 
-        // Load the variable to access
-        if let Some(err) = self.execute_expression(lhs) {
-            return Some(err);
-        }
+        self.frame_base = caller_frame_base;
+        self.loop_depth = caller_loop_depth;
+        self.call_depth -= 1;
 
-        // Get the item
-        let lhs_item = match self.op_stack.pop() {
-            None => {
-                return Some(ExecutionError::StackError);
+        match result {
+            Some(Ok(value)) => {
+                self.op_stack.push(Rc::new(RefCell::new(value)));
+                None
             }
-            Some(val) => { 
-                val.borrow().get_value()
-             }
-        };
-
-        // Load the variable to access
-        if let Some(err) = self.execute_expression(rhs) {
-            return Some(err);
+            Some(Err(e)) => { Some(e) }
+            None => { None }
         }
+    }
 
-        // Get the item
-        let rhs_item = match self.op_stack.pop() {
-            None => {
-                return Some(ExecutionError::StackError);
-            }
-            Some(val) => { 
-                val.borrow().get_value()
-             }
-        };
+    /// Process a call to a built in function. Functions registered by the host are
+    /// checked after the functions that are built into the language
+    #[allow(clippy::vec_box)]
+    fn process_built_in(&mut self, function_name: String, params: Vec<Box<Expr>>) -> Option<ExecutionError> {
 
-        // Figure out what is going on
-        /*
-            This block of code determines elevation of items in an expression to other types
+        match function_name.as_str() {
 
-            By figuring out what type the lhs and rhs of the expression we promote what needs
-            promotion and then call on the actual op executors for the given type
-        */
-        match lhs_item {
+            "drop" => {
 
-            RecordData::Integer(v_lhs) => {
-                match rhs_item {
-                    RecordData::Integer(v_rhs) => {
-                        
-                        let result = self.execute_op_integer(v_lhs, v_rhs, op);
-                        self.op_stack.push(Rc::new(RefCell::new(
-                            RecordData::Integer( result )
-                        )));
-                        None
-                    }
-                    RecordData::Float(v_rhs) => {
-                        let mut vf_lhs = Float::new(FLOAT_PRECISION);
-                        vf_lhs.assign(v_lhs);
-
-                        let result = self.execute_op_float(vf_lhs, v_rhs, op);
-                        self.op_stack.push(Rc::new(RefCell::new(
-                            RecordData::Float( result )
-                        )));
-                        None
-                    }
-                    RecordData::String(v_rhs) => {
-                        let v_lhs = String::from(v_lhs.to_string_radix(RADIX));
-                        return self.execute_op_string(v_lhs, v_rhs, op);
-                    }
-                    RecordData::Dict(_) => {
-                        return Some(ExecutionError::InvalidOperation("No valid operation for type Dictionary"));
+                // Drop works on the variable itself, not its value
+                let variable = match params.as_slice() {
+                    [param] => {
+                        match param.unlocated() {
+                            Expr::Variable(VariableType::Singular(variable)) => { variable.clone() }
+                            _ => { return Some(ExecutionError::InvalidParameters); }
+                        }
                     }
-                }
-            }
+                    _ => { return Some(ExecutionError::InvalidParameters); }
+                };
 
-            RecordData::Float(v_lhs) => {
-                match rhs_item {
-                    RecordData::Integer(v_rhs) => {
-                        let mut vf_rhs = Float::new(FLOAT_PRECISION);
-                        vf_rhs.assign(v_rhs);
-                        let result = self.execute_op_float(v_lhs, vf_rhs, op);
-                        self.op_stack.push(Rc::new(RefCell::new(
-                            RecordData::Float( result )
-                        )));
-                        None
-                    }
-                    RecordData::Float(v_rhs) => {
-                        let result = self.execute_op_float(v_lhs, v_rhs, op);
-                        self.op_stack.push(Rc::new(RefCell::new(
-                            RecordData::Float( result )
-                        )));
+                match self.rm_record(&variable) {
+                    Some(e) => Some(e),
+                    None    => {
+                        self.op_stack.push(Rc::new(RefCell::new(RecordData::Bool(true))));
                         None
                     }
-                    RecordData::String(v_rhs) => {
-                        let vs_lhs = String::from(v_lhs.to_string_radix(RADIX, Some(v_lhs.prec() as usize)));
-                        return self.execute_op_string(vs_lhs, v_rhs, op);
-                    }
-                    RecordData::Dict(_) => {
-                        return Some(ExecutionError::InvalidOperation("No valid operation for type Dictionary"));
-                    }
-                }
-            }
-
-            RecordData::String(v_lhs) => {
-                match rhs_item {
-                    RecordData::Integer(v_rhs) => {
-                        let v_rhs = String::from(v_rhs.to_string_radix(RADIX));
-                        return self.execute_op_string(v_lhs, v_rhs, op);
-                    }
-                    RecordData::Float(v_rhs) => {
-                        let vs_rhs = String::from(v_rhs.to_string_radix(RADIX, Some(v_rhs.prec() as usize)));
-                        return self.execute_op_string(v_lhs, vs_rhs, op);
-                    }
-                    RecordData::String(v_rhs) => {
-                        return self.execute_op_string(v_lhs, v_rhs, op);
-                    }
-                    RecordData::Dict(_) => {
-                        return Some(ExecutionError::InvalidOperation("No valid operation for type Dictionary"));
-                    }
                 }
             }
 
-            RecordData::Dict(_) => {
-                return Some(ExecutionError::InvalidOperation("No valid operation for type Dictionary"));
-            }
+            "precision" => {
 
-        }
-    }
+                let param_count = params.len();
 
-    /// Execute a string operation
-    fn execute_op_string(&mut self, lhs: String, rhs: String, op: Opcode) -> Option<ExecutionError> {
-        
-        match op {
-            Opcode::Add => {
-                self.op_stack.push(Rc::new(RefCell::new(RecordData::String( lhs + rhs.as_str() ))));
-            }
+                let arguments = match self.evaluate_method_params(params, param_count) {
+                    Ok(arguments) => { arguments }
+                    Err(e) => { return Some(e); }
+                };
 
-            _ => {
-                return Some(ExecutionError::InvalidOperation("Invalid operation for type String"));
+                match ops::precision(&mut self.precision, arguments) {
+                    Ok(Some(value)) => { self.op_stack.push(Rc::new(RefCell::new(value))); }
+                    Ok(None) => { }
+                    Err(e) => { return Some(e); }
+                }
+                None
             }
-        }
 
-        None
-    }
+            "auto_vivify" => {
 
-    /// Execute an integer operation
-    fn execute_op_integer(&mut self, lhs: Integer, rhs: Integer, op: Opcode) -> Integer {
+                let param_count = params.len();
 
-        match op {
-            Opcode::Mul => {
-                //println!("mul");
-                return lhs * rhs;
-            }
-            Opcode::Div => {
-                //println!("div");
-                return lhs / rhs;
-            }
-            Opcode::Add => {
-                //println!("add");
-                return lhs + rhs;
-            }
-            Opcode::Sub => {
-                //println!("sub");
-                return lhs - rhs;
-            }
-            Opcode::Lte => {
-                return Integer::from(lhs <= rhs);
-            }
+                let arguments = match self.evaluate_method_params(params, param_count) {
+                    Ok(arguments) => { arguments }
+                    Err(e) => { return Some(e); }
+                };
 
-            Opcode::Gte => {
-                return Integer::from(lhs >= rhs);
+                match ops::auto_vivify(&mut self.auto_vivify, arguments) {
+                    Ok(Some(value)) => { self.op_stack.push(Rc::new(RefCell::new(value))); }
+                    Ok(None) => { }
+                    Err(e) => { return Some(e); }
+                }
+                None
             }
 
-            Opcode::Lt => {
-                return Integer::from(lhs < rhs);
-            }
+            name if math::is_function(name) => {
 
-            Opcode::Gt => {
-                return Integer::from(lhs > rhs);
-            }
+                let param_count = params.len();
 
-            Opcode::Equal => {
-                return Integer::from(lhs == rhs);
-            }
+                let arguments = match self.evaluate_method_params(params, param_count) {
+                    Ok(arguments) => { arguments }
+                    Err(e) => { return Some(e); }
+                };
 
-            Opcode::Ne => {
-                return Integer::from(lhs != rhs);
+                match math::call(name, arguments, self.precision) {
+                    Ok(value) => {
+                        self.op_stack.push(Rc::new(RefCell::new(value)));
+                        None
+                    }
+                    Err(e) => { Some(e) }
+                }
             }
 
-            Opcode::Pow => {
+            _ => {
 
-                // Rust pow for i64 requires a u32 so we attempt to convert it to a u32
-                // if it fails a PANIC!
-                let rhs_converted = match u32::try_from(rhs) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u32 for \"pow\": {}", e);
-                    }
+                let function = match self.host_functions.get(&function_name) {
+                    Some(function) => { function.clone() }
+                    None => { return Some(ExecutionError::UnknownBuiltInFunction(function_name)); }
                 };
 
-                return Integer::from(lhs.pow(rhs_converted));
-        
-            }
+                let param_count = params.len();
 
-            Opcode::Mod => {
-                return lhs % rhs;
-            }
+                let arguments = match self.evaluate_method_params(params, param_count) {
+                    Ok(arguments) => { arguments }
+                    Err(e) => { return Some(e); }
+                };
 
-            Opcode::Lsh => {
+                let arguments = arguments.iter().map(Value::from_record).collect();
 
-                let rhs_converted = match u64::try_from(rhs) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"lsh\": {}", e);
-                    }
-                };
+                match function.call(arguments, self.precision) {
 
-                let lhs_converted = match u64::try_from(lhs) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"lsh\": {}", e);
-                    }
-                };
+                    Ok(Some(value)) => {
 
-                return Integer::from(lhs_converted << rhs_converted);
+                        match value.into_record() {
+                            Some(record) => { self.op_stack.push(Rc::new(RefCell::new(record))); }
+                            None => { return Some(ExecutionError::InvalidOperation("Host functions can not return functions")); }
+                        }
+                        None
+                    }
+                    Ok(None) => { None }
+                    Err(e) => { Some(e) }
+                }
             }
+        }
+    }
 
-            Opcode::Rsh => {
+    /// Perform actions on an accessor
+    fn perform_access(&mut self, item_expr: Expr, accessor: Accessors, method: MemberMethod) -> Option<ExecutionError> {
 
-                let rhs_converted = match u64::try_from(rhs) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"rhs\": {}", e);
-                    }
-                };
+        // Load the variable to access
+        if let Some(err) = self.execute_expression(item_expr) {
+            return Some(err);
+        }
 
-                let lhs_converted = match u64::try_from(lhs) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"rhs\": {}", e);
-                    }
-                };
-                
-                return Integer::from(lhs_converted >> rhs_converted);
+        // Get the item
+        let accessed_item = match self.op_stack.pop() {
+            None => {
+                return Some(ExecutionError::StackError);
             }
 
-            Opcode::BwXor => {
-                return lhs ^ rhs
-            }
+            Some(val) => { 
+                val
+             }
+        };
 
-            Opcode::BwOr => {
-                return lhs | rhs;
-            }
+        match accessor {
 
-            Opcode::BwAnd => {
-                return lhs & rhs;
-            }
+            Accessors::Dot => {
 
-            Opcode::Or => {
+                let param_count = method.params.len();
 
-                if lhs > 0 || rhs > 0{
-                    return Integer::from(1);
-                }
-                return Integer::from(0);
-            }
+                let arguments = match self.evaluate_method_params(method.params, param_count) {
+                    Ok(arguments) => { arguments }
+                    Err(e) => { return Some(e); }
+                };
 
-            Opcode::And => {
-                
-                if lhs > 0 && rhs > 0{
-                    return Integer::from(1);
+                match ops::call_method(&accessed_item, &method.method, arguments, self.precision) {
+                    Ok(Some(value)) => { self.op_stack.push(Rc::new(RefCell::new(value))); }
+                    Ok(None) => { }
+                    Err(e) => { return Some(e); }
                 }
-                return Integer::from(0);
+                None
             }
         }
     }
 
-    /// Execute a float operation
-    fn execute_op_float(&mut self, lhs: Float, rhs: Float, op: Opcode) -> Float  {
-        
-        match op {
-            Opcode::Mul => {
-                //println!("mul");
-                return lhs * rhs;
-            }
-            Opcode::Div => {
-                //println!("div");
-                return lhs / rhs;
-            }
-            Opcode::Add => {
-                //println!("add");
-                return lhs + rhs;
-            }
-            Opcode::Sub => {
-                //println!("sub");
-                return lhs - rhs;
-            }
-            Opcode::Lte => {
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs <= rhs));
-                return val;
-            }
+    /// Evaluate the parameters given to a method, ensuring the expected amount were given
+    #[allow(clippy::vec_box)]
+    fn evaluate_method_params(&mut self, params: Vec<Box<Expr>>, expected: usize) -> Result<Vec<RecordData>, ExecutionError> {
 
-            Opcode::Gte => {
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs >= rhs));
-                return val;
-            }
+        if params.len() != expected {
+            return Err(ExecutionError::InvalidParameters);
+        }
 
-            Opcode::Lt => {
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs < rhs));
-                return val;
-            }
+        let mut values = Vec::new();
 
-            Opcode::Gt => {
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs > rhs));
-                return val;
-            }
+        for param in params {
 
-            Opcode::Equal => {
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs == rhs));
-                return val;
+            if let Some(e) = self.execute_expression(*param) {
+                return Err(e);
             }
 
-            Opcode::Ne => {
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs != rhs));
-                return val;
+            match self.op_stack.pop() {
+                Some(val) => { values.push(val.borrow().get_value()); }
+                None => { return Err(ExecutionError::StackError); }
             }
+        }
 
-            Opcode::Pow => {
+        Ok(values)
+    }
 
-                // Rust pow for i64 requires a u32 so we attempt to convert it to a u32
-                // if it fails a PANIC!
-                let rhs_converted = rhs.to_f64();
-                
-                return Float::from(lhs.pow(rhs_converted));
-            }
+    /// Evaluate an expression down to its value
+    fn evaluate(&mut self, expression: Expr) -> Result<RecordData, ExecutionError> {
 
-            Opcode::Mod => {
-                return lhs % rhs;
-            }
+        if let Some(e) = self.execute_expression(expression) {
+            return Err(e);
+        }
 
-            Opcode::Lsh => {
+        match self.op_stack.pop() {
+            Some(val) => { Ok(val.borrow().get_value()) }
+            None => { Err(ExecutionError::StackError) }
+        }
+    }
 
-                let rhs_converted = match u64::try_from(rhs.to_integer().unwrap()) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"lsh\": {}", e);
-                    }
-                };
+    /// Perform a unary operation
+    fn perform_unary(&mut self, expression: Expr, op: UnaryOpcode) -> Option<ExecutionError> {
 
-                let lhs_converted = match u64::try_from(lhs.to_integer().unwrap()) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"lsh\": {}", e);
-                    }
-                };
+        // Load the variable to access
+        if let Some(err) = self.execute_expression(expression) {
+            return Some(err);
+        }
 
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs_converted << rhs_converted));
-                return val;
+        // Get the item
+        let item = match self.op_stack.pop() {
+            None => {
+                return Some(ExecutionError::StackError);
             }
 
-            Opcode::Rsh => {
-
-                let rhs_converted = match u64::try_from(rhs.to_integer().unwrap()) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"rhs\": {}", e);
-                    }
-                };
+            Some(val) => { 
+                val.borrow().get_value()
+             }
+        };
 
-                let lhs_converted = match u64::try_from(lhs.to_integer().unwrap()) {
-                    Ok(r) => { r }
-                    Err(e) => {
-                        panic!("Unable to convert value into u64 for \"rhs\": {}", e);
-                    }
-                };
-                
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(lhs_converted >> rhs_converted));
-                return val;
+        match ops::unary(item, op) {
+            Ok(value) => {
+                self.op_stack.push(Rc::new(RefCell::new(value)));
+                None
             }
+            Err(e) => { Some(e) }
+        }
+    }
 
-            Opcode::BwXor => {
-                return Float::with_val(FLOAT_PRECISION, lhs.to_integer().unwrap() ^ rhs.to_integer().unwrap() );
-            }
+    /// Perform an opcode
+    pub fn perform_opcode(&mut self, lhs: Expr, rhs: Expr, op: Opcode) -> Option<ExecutionError> {
 
-            Opcode::BwOr => {
-                return Float::with_val(FLOAT_PRECISION, lhs.to_integer().unwrap() | rhs.to_integer().unwrap() );
+        // Load the variable to access
+        if let Some(err) = self.execute_expression(lhs) {
+            return Some(err);
+        }
+
+        // Get the item
+        let lhs_item = match self.op_stack.pop() {
+            None => {
+                return Some(ExecutionError::StackError);
             }
+            Some(val) => { 
+                val.borrow().get_value()
+             }
+        };
 
-            Opcode::BwAnd => {
-                return Float::with_val(FLOAT_PRECISION, lhs.to_integer().unwrap() & rhs.to_integer().unwrap() );
+        // The right hand side of a logic operation is only evaluated if the left hand side doesn't decide the result
+        match op {
+            Opcode::And if !lhs_item.is_truthy() => {
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::Bool(false))));
+                return None;
+            }
+            Opcode::Or if lhs_item.is_truthy() => {
+                self.op_stack.push(Rc::new(RefCell::new(RecordData::Bool(true))));
+                return None;
             }
+            _ => { }
+        }
 
-            Opcode::Or => {
+        // Load the variable to access
+        if let Some(err) = self.execute_expression(rhs) {
+            return Some(err);
+        }
 
-                if lhs > 0 || rhs > 0 {
-                    let mut val = Float::new(FLOAT_PRECISION);
-                    val.assign(Integer::from(1));
-                    return val;
-                }
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(0));
-                return val;
+        // Get the item
+        let rhs_item = match self.op_stack.pop() {
+            None => {
+                return Some(ExecutionError::StackError);
             }
+            Some(val) => { 
+                val.borrow().get_value()
+             }
+        };
 
-            Opcode::And => {
-                
-                if lhs > 0 && rhs > 0{
-                    let mut val = Float::new(FLOAT_PRECISION);
-                    val.assign(Integer::from(1));
-                    return val;
-                }
-                let mut val = Float::new(FLOAT_PRECISION);
-                val.assign(Integer::from(0));
-                return val;
+        match ops::binary(lhs_item, rhs_item, op, self.precision) {
+            Ok(value) => {
+                self.op_stack.push(Rc::new(RefCell::new(value)));
+                None
             }
+            Err(e) => { Some(e) }
         }
     }
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 
 use derive_more::Display;
 
-#[derive(Display)]
+#[derive(Debug, Display)]
 pub enum ExecutionError {
 
     StackError,
@@ -23,5 +23,39 @@ pub enum ExecutionError {
 
     InvalidParameters,
 
-    IndexError
-}
\ No newline at end of file
+    IndexError,
+
+    #[display(fmt = "Key '{}' does not exist", _0)]
+    MissingKey(String),
+
+    #[display(fmt = "Division by zero")]
+    DivisionByZero,
+
+    #[display(fmt = "Overflow: result of '{}' is too large", _0)]
+    Overflow(&'static str),
+
+    #[display(fmt = "Shift amount {} is out of range", _0)]
+    ShiftOutOfRange(rug::Integer),
+
+    #[display(fmt = "Negative exponent in integer power")]
+    NegativeExponent,
+
+    #[display(fmt = "Value {} is not a finite number", _0)]
+    NotFinite(String),
+
+    #[display(fmt = "Maximum call depth of {} exceeded", _0)]
+    CallDepthExceeded(usize),
+
+    #[display(fmt = "'{}' is a built in function and can not be registered by the host", _0)]
+    ReservedFunctionName(String)
+}
+/// Errors that can come from evaluating source code
+#[derive(Debug, Display)]
+pub enum EvalError {
+
+    #[display(fmt = "Parse Error: {}", _0)]
+    ParseError(String),
+
+    #[display(fmt = "Execution Error: {}", _0)]
+    ExecutionError(ExecutionError),
+}
@@ -0,0 +1,235 @@
+/*
+    Native functions that the host application can register with the engine.
+
+    Any closure taking up to six arguments that implement FromValue, and returning something that 
+    implements IntoReturn, can be registered. The arguments are converted from the values given by 
+    the script before the closure is called, so the host never has to pick apart a Value by hand
+    unless it wants to.
+*/
+
+use std::rc::Rc;
+use std::fmt;
+use rug::{ Integer, Float, Rational, Complex };
+
+use micron_ast::FLOAT_PRECISION;
+
+use crate::error::ExecutionError;
+use crate::value::Value;
+use crate::math;
+
+/// Built in functions that aren't part of the math library
+const BUILT_INS: [&str; 3] = [ "drop", "precision", "auto_vivify" ];
+
+/// Check if a name belongs to a built in function. Built ins are looked up before host 
+/// functions, so a host function given one of these names could never be called
+pub(crate) fn is_built_in(name: &str) -> bool {
+    BUILT_INS.contains(&name) || math::is_function(name)
+}
+
+/// What comes back from a host function once it has been converted for the engine
+pub type HostResult = Result<Option<Value>, ExecutionError>;
+
+/// A registered function after its arguments and return have been erased to values
+#[derive(Clone)]
+pub struct NativeFunction {
+    function: Rc<dyn Fn(Vec<Value>, u32) -> HostResult>
+}
+
+impl NativeFunction {
+
+    /// Call the function with the values given by a script, converting them at the given precision
+    pub(crate) fn call(&self, args: Vec<Value>, precision: u32) -> HostResult {
+        (self.function)(args, precision)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFunction")
+    }
+}
+
+/// Types that can be taken as an argument by a host function
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, ExecutionError>;
+
+    /// Convert with the precision, in bits, of the engine calling the function. Only types 
+    /// that are built at a precision need to implement this
+    fn from_value_at(value: Value, _precision: u32) -> Result<Self, ExecutionError> {
+        Self::from_value(value)
+    }
+}
+
+/// Types that can be returned by a host function. Returning nothing leaves nothing for the script
+pub trait IntoReturn {
+    fn into_return(self) -> HostResult;
+}
+
+/// Closures that can be registered as host functions
+pub trait HostFunction<Args> {
+    fn into_native(self) -> NativeFunction;
+}
+
+fn conversion_failure(expected: &str, value: &Value) -> ExecutionError {
+    ExecutionError::ConversionFailure(
+        "Host function argument".to_string(), 
+        format!("Expected {} but was given {:?}", expected, value)
+    )
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        Ok(value)
+    }
+}
+
+impl FromValue for Integer {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Integer(v) => Ok(v),
+            other => Err(conversion_failure("an integer", &other))
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Integer(v) => v.to_i64().ok_or_else(|| conversion_failure("an integer that fits in 64 bits", &Value::Integer(v))),
+            other => Err(conversion_failure("an integer", &other))
+        }
+    }
+}
+
+impl FromValue for Float {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Float(v) => Ok(v),
+            other => Err(conversion_failure("a float", &other))
+        }
+    }
+}
+
+/// Integers are accepted as well, since any integer is also a rational
+impl FromValue for Rational {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Rational(v) => Ok(v),
+            Value::Integer(v)  => Ok(Rational::from(v)),
+            other => Err(conversion_failure("a rational", &other))
+        }
+    }
+}
+
+/// Real numbers are accepted as well, and made complex at the precision of the engine
+impl FromValue for Complex {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        Self::from_value_at(value, FLOAT_PRECISION)
+    }
+
+    fn from_value_at(value: Value, precision: u32) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Complex(v)  => Ok(v),
+            Value::Integer(v)  => Ok(Complex::with_val(precision, v)),
+            Value::Float(v)    => Ok(Complex::with_val(precision, v)),
+            Value::Rational(v) => Ok(Complex::with_val(precision, v)),
+            other => Err(conversion_failure("a complex number", &other))
+        }
+    }
+}
+
+/// Integers and rationals are accepted as well so scripts don't need to write '2.0' for '2'
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Float(v)    => Ok(v.to_f64()),
+            Value::Integer(v)  => Ok(v.to_f64()),
+            Value::Rational(v) => Ok(v.to_f64()),
+            other => Err(conversion_failure("a number", &other))
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Bool(v) => Ok(v),
+            other => Err(conversion_failure("a bool", &other))
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::String(v) => Ok(v),
+            other => Err(conversion_failure("a string", &other))
+        }
+    }
+}
+
+impl FromValue for Vec<Value> {
+    fn from_value(value: Value) -> Result<Self, ExecutionError> {
+        match value {
+            Value::List(v) => Ok(v),
+            other => Err(conversion_failure("a list", &other))
+        }
+    }
+}
+
+impl IntoReturn for () {
+    fn into_return(self) -> HostResult {
+        Ok(None)
+    }
+}
+
+impl<T: Into<Value>> IntoReturn for T {
+    fn into_return(self) -> HostResult {
+        Ok(Some(self.into()))
+    }
+}
+
+impl<T: IntoReturn> IntoReturn for Result<T, ExecutionError> {
+    fn into_return(self) -> HostResult {
+        self?.into_return()
+    }
+}
+
+macro_rules! impl_host_function {
+    ($($arg:ident $name:ident),*) => {
+        impl<Func, Ret, $($arg,)*> HostFunction<($($arg,)*)> for Func
+        where
+            Func: Fn($($arg),*) -> Ret + 'static,
+            Ret: IntoReturn,
+            $($arg: FromValue,)*
+        {
+            fn into_native(self) -> NativeFunction {
+                NativeFunction {
+                    function: Rc::new(move |args: Vec<Value>, precision: u32| {
+
+                        let expected: &[&str] = &[$(stringify!($arg)),*];
+                        if args.len() != expected.len() {
+                            return Err(ExecutionError::InvalidParameters);
+                        }
+
+                        // Neither is used by a function that takes no arguments
+                        #[allow(unused_mut, unused_variables)]
+                        let (mut args, precision) = (args.into_iter(), precision);
+                        $(
+                            let $name = $arg::from_value_at(args.next().unwrap(), precision)?;
+                        )*
+                        (self)($($name),*).into_return()
+                    })
+                }
+            }
+        }
+    };
+}
+
+impl_host_function!();
+impl_host_function!(A a);
+impl_host_function!(A a, B b);
+impl_host_function!(A a, B b, C c);
+impl_host_function!(A a, B b, C c, D d);
+impl_host_function!(A a, B b, C c, D d, E e);
+impl_host_function!(A a, B b, C c, D d, E e, F f);
@@ -1,10 +1,28 @@
 
 #[allow(dead_code)]
 mod types;
+mod ops;
+pub use ops::{ MAX_CALL_DEPTH, STACK_SIZE };
+
+mod math;
+mod strings;
+mod compare;
 
 #[allow(dead_code)]
 mod engine;
 pub use engine::Engine;
 
+mod bytecode;
+mod compiler;
+
+mod vm;
+pub use vm::Vm;
+
 mod error;
-pub use error::ExecutionError;
+pub use error::{ ExecutionError, EvalError };
+
+mod value;
+pub use value::{ Value, Key };
+
+mod host;
+pub use host::{ FromValue, IntoReturn, HostFunction, HostResult, NativeFunction };
@@ -0,0 +1,270 @@
+
+/*
+    The math library. These are built in functions called with '#' like any other built in, ie '#sqrt(2)'.
+
+    Integers and rationals given to a function that needs a float are promoted at the precision of
+    the engine, the same way they are when used with a float in an operation. Floats keep the
+    precision they already have. Complex numbers are accepted wherever MPFR has a complex version
+    of the function, and the result stays complex
+*/
+
+use rug::{ Integer, Float, Complex, float::Constant, integer::IsPrime };
+
+use crate::types::RecordData;
+use crate::error::ExecutionError;
+use crate::ops::{ self, expect_params, MAX_INTEGER_BITS };
+use micron_ast::Opcode;
+
+/// Every function in the library
+const FUNCTIONS: [&str; 24] = [
+    "sqrt", "ln", "log10", "exp",
+    "sin", "cos", "tan", "asin", "acos", "atan",
+    "floor", "ceil", "round", "trunc",
+    "abs", "min", "max",
+    "gcd", "lcm", "factorial", "is_prime", "next_prime",
+    "pi", "e"
+];
+
+/// Number of Miller-Rabin rounds used to decide if an integer is prime
+const PRIME_ROUNDS: u32 = 30;
+
+/// Check if a built in function is part of the math library
+pub(crate) fn is_function(name: &str) -> bool {
+    FUNCTIONS.contains(&name)
+}
+
+/// Call a function in the math library
+pub(crate) fn call(name: &str, args: Vec<RecordData>, precision: u32) -> Result<RecordData, ExecutionError> {
+
+    match name {
+
+        "pi" => {
+            expect_params(&args, 0)?;
+            return Ok(RecordData::Float(Float::with_val(precision, Constant::Pi)));
+        }
+
+        "e" => {
+            expect_params(&args, 0)?;
+            return Ok(RecordData::Float(Float::with_val(precision, 1).exp()));
+        }
+
+        "min" | "max" => {
+            return extreme(name, args, precision);
+        }
+
+        "gcd" | "lcm" => {
+
+            let mut integers = integers(name, args)?;
+
+            if integers.is_empty() {
+                return Err(ExecutionError::InvalidParameters);
+            }
+
+            let first = integers.remove(0);
+
+            let result = integers.into_iter().fold(first, |result, next| {
+                if name == "gcd" { result.gcd(&next) } else { result.lcm(&next) }
+            });
+
+            return Ok(RecordData::Integer(result.abs()));
+        }
+
+        _ => { }
+    }
+
+    // Everything else works on a single number
+    expect_params(&args, 1)?;
+    let item = args.into_iter().next().unwrap_or(RecordData::Nil);
+
+    match name {
+
+        "floor" | "ceil" | "round" | "trunc" => {
+            whole(name, item)
+        }
+
+        "abs" => {
+
+            match item {
+                RecordData::Integer(v)  => { Ok(RecordData::Integer(v.abs())) }
+                RecordData::Float(v)    => { Ok(RecordData::Float(v.abs())) }
+                RecordData::Rational(v) => { Ok(RecordData::Rational(v.abs())) }
+                RecordData::Complex(v)  => { Ok(RecordData::Float(v.abs().into_real_imag().0)) }
+                _ => { Err(expected_number(name)) }
+            }
+        }
+
+        "factorial" => {
+
+            let n = integer(name, item)?;
+
+            if n < 0 {
+                return Err(ExecutionError::InvalidOperation("Factorial is only defined for integers that are not negative"));
+            }
+
+            // n! is below n ** n, so make sure that would fit before asking rug to build it
+            match n.to_u32() {
+                Some(v) if (n.significant_bits() as u64) * (v as u64) <= MAX_INTEGER_BITS => {
+                    Ok(RecordData::Integer(Integer::from(Integer::factorial(v))))
+                }
+                _ => {
+                    Err(ExecutionError::Overflow("factorial"))
+                }
+            }
+        }
+
+        "is_prime" => {
+
+            let n = integer(name, item)?;
+
+            // Rug would check the absolute value, but primes are positive
+            if n < 2 {
+                return Ok(RecordData::Bool(false));
+            }
+            Ok(RecordData::Bool(n.is_probably_prime(PRIME_ROUNDS) != IsPrime::No))
+        }
+
+        "next_prime" => {
+            Ok(RecordData::Integer(integer(name, item)?.next_prime()))
+        }
+
+        _ => {
+            transcendental(name, item, precision)
+        }
+    }
+}
+
+/// Functions that give back a float, or a complex number when given one. Outside of the
+/// domain of a real function the result is NaN, just as MPFR hands back
+fn transcendental(name: &str, item: RecordData, precision: u32) -> Result<RecordData, ExecutionError> {
+
+    let value = match item {
+        RecordData::Integer(v)  => { Float::with_val(precision, v) }
+        RecordData::Rational(v) => { Float::with_val(precision, v) }
+        RecordData::Float(v)    => { v }
+        RecordData::Complex(v)  => { return complex(name, v); }
+        _ => { return Err(expected_number(name)); }
+    };
+
+    let result = match name {
+        "sqrt"  => { value.sqrt() }
+        "ln"    => { value.ln() }
+        "log10" => { value.log10() }
+        "exp"   => { value.exp() }
+        "sin"   => { value.sin() }
+        "cos"   => { value.cos() }
+        "tan"   => { value.tan() }
+        "asin"  => { value.asin() }
+        "acos"  => { value.acos() }
+        "atan"  => { value.atan() }
+        _ => { return Err(ExecutionError::UnknownBuiltInFunction(name.to_string())); }
+    };
+
+    Ok(RecordData::Float(result))
+}
+
+/// The complex versions of the transcendental functions
+fn complex(name: &str, value: Complex) -> Result<RecordData, ExecutionError> {
+
+    let result = match name {
+        "sqrt"  => { value.sqrt() }
+        "ln"    => { value.ln() }
+        "log10" => { value.log10() }
+        "exp"   => { value.exp() }
+        "sin"   => { value.sin() }
+        "cos"   => { value.cos() }
+        "tan"   => { value.tan() }
+        "asin"  => { value.asin() }
+        "acos"  => { value.acos() }
+        "atan"  => { value.atan() }
+        _ => { return Err(ExecutionError::UnknownBuiltInFunction(name.to_string())); }
+    };
+
+    Ok(RecordData::Complex(result))
+}
+
+/// Round a number to an integer. Integers are already whole and are given back as they are
+fn whole(name: &str, item: RecordData) -> Result<RecordData, ExecutionError> {
+
+    match item {
+
+        RecordData::Integer(v) => {
+            Ok(RecordData::Integer(v))
+        }
+
+        RecordData::Float(v) => {
+
+            let rounded = match name {
+                "floor" => { v.floor() }
+                "ceil"  => { v.ceil() }
+                "round" => { v.round() }
+                _       => { v.trunc() }
+            };
+            Ok(RecordData::Integer(ops::float_to_integer(&rounded)?))
+        }
+
+        RecordData::Rational(v) => {
+
+            let rounded = match name {
+                "floor" => { v.floor() }
+                "ceil"  => { v.ceil() }
+                "round" => { v.round() }
+                _       => { v.trunc() }
+            };
+            Ok(RecordData::Integer(rounded.into_numer_denom().0))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Only real numbers can be rounded"))
+        }
+    }
+}
+
+/// Find the smallest or largest of the given items, or of the items in a single list.
+/// Items are compared the same way '<' compares them
+fn extreme(name: &str, args: Vec<RecordData>, precision: u32) -> Result<RecordData, ExecutionError> {
+
+    let items = match args.as_slice() {
+        [RecordData::List(list)] => {
+            list.iter().map(|item| item.borrow().get_value()).collect()
+        }
+        _ => { args }
+    };
+
+    let mut items = items.into_iter();
+
+    let mut result = match items.next() {
+        Some(item) => { item }
+        None => { return Err(ExecutionError::InvalidParameters); }
+    };
+
+    let op = if name == "min" { Opcode::Lt } else { Opcode::Gt };
+
+    for item in items {
+        if ops::binary(item.clone(), result.clone(), op, precision)?.is_truthy() {
+            result = item;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Get an integer out of an item for the functions that only work on integers
+fn integer(name: &str, item: RecordData) -> Result<Integer, ExecutionError> {
+
+    match item {
+        RecordData::Integer(v) => { Ok(v) }
+        _ => {
+            Err(ExecutionError::ConversionFailure(name.to_string(), "Expected an integer".to_string()))
+        }
+    }
+}
+
+/// Get integers out of every item given
+fn integers(name: &str, args: Vec<RecordData>) -> Result<Vec<Integer>, ExecutionError> {
+    args.into_iter().map(|item| integer(name, item)).collect()
+}
+
+/// Error for a function given something other than a number
+fn expected_number(name: &str) -> ExecutionError {
+    ExecutionError::ConversionFailure(name.to_string(), "Expected a number".to_string())
+}
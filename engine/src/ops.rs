@@ -0,0 +1,1360 @@
+/*
+
+    Operations on values that are shared by the tree walking engine and the virtual machine.
+    Keeping them in one place means both backends promote, convert and fail in exactly the same way
+*/
+
+use std::convert::TryFrom;
+use rug::{Integer, Float, Rational, Complex, ops::Pow};
+use std::{ cell::RefCell, rc::Rc };
+
+use micron_ast::{ Opcode, UnaryOpcode, FormatSpec, Alignment, RADIX };
+
+use crate::types::{ RecordData, List, Dictionary, complex_string };
+use crate::error::ExecutionError;
+use crate::strings;
+use crate::compare;
+use crate::value::Key;
+
+/// The largest integer, in bits, that an operation is allowed to produce. Anything past this
+/// is treated as an overflow rather than letting GMP try (and fail) to allocate it
+pub(crate) const MAX_INTEGER_BITS: u64 = 1 << 26;
+
+/// The most function calls that can be in progress at once. Calling past this is an error
+/// rather than recursing until the host runs out of memory
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// Stack size, in bytes, a host should give the thread running the tree walking engine. 
+/// The engine recurses through the host's stack for every call, so the default stack of a
+/// thread can run out before MAX_CALL_DEPTH is reached
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Perform a binary operation
+pub(crate) fn binary(lhs: RecordData, rhs: RecordData, op: Opcode, precision: u32) -> Result<RecordData, ExecutionError> {
+
+    /*
+        This block of code determines elevation of items in an expression to other types
+
+        By figuring out what type the lhs and rhs of the expression we promote what needs
+        promotion and then call on the actual op executors for the given type. Anything 
+        promoted to a float or complex number is given the precision of the engine
+    */
+
+    // Logic operations work on the truthiness of any type, so they never need promotion
+    match op {
+        Opcode::Or => {
+            return Ok(RecordData::Bool(lhs.is_truthy() || rhs.is_truthy()));
+        }
+
+        Opcode::And => {
+            return Ok(RecordData::Bool(lhs.is_truthy() && rhs.is_truthy()));
+        }
+
+        // Neither do comparisons, promoting a number could change its value
+        Opcode::Equal => {
+            return Ok(RecordData::Bool(compare::equal(&lhs, &rhs)));
+        }
+
+        Opcode::Ne => {
+            return Ok(RecordData::Bool(!compare::equal(&lhs, &rhs)));
+        }
+
+        Opcode::Lt | Opcode::Lte | Opcode::Gt | Opcode::Gte => {
+            return Ok(RecordData::Bool(compare::order(&lhs, &rhs, op)?));
+        }
+
+        _ => { }
+    }
+
+    match (lhs, rhs) {
+
+        (RecordData::Integer(v_lhs), RecordData::Integer(v_rhs)) => {
+            integer_op(v_lhs, v_rhs, op)
+        }
+
+        (RecordData::Integer(v_lhs), RecordData::Float(v_rhs)) => {
+            let vf_lhs = Float::with_val(precision, v_lhs);
+            float_op(vf_lhs, v_rhs, op, precision)
+        }
+
+        (RecordData::Float(v_lhs), RecordData::Integer(v_rhs)) => {
+            let vf_rhs = Float::with_val(precision, v_rhs);
+            float_op(v_lhs, vf_rhs, op, precision)
+        }
+
+        (RecordData::Float(v_lhs), RecordData::Float(v_rhs)) => {
+            float_op(v_lhs, v_rhs, op, precision)
+        }
+
+        // Integers become rationals so the result stays exact, but a float makes anything inexact
+        (RecordData::Integer(v_lhs), RecordData::Rational(v_rhs)) => {
+            rational_op(Rational::from(v_lhs), v_rhs, op, precision)
+        }
+
+        (RecordData::Rational(v_lhs), RecordData::Integer(v_rhs)) => {
+            rational_op(v_lhs, Rational::from(v_rhs), op, precision)
+        }
+
+        (RecordData::Rational(v_lhs), RecordData::Rational(v_rhs)) => {
+            rational_op(v_lhs, v_rhs, op, precision)
+        }
+
+        (RecordData::Rational(v_lhs), RecordData::Float(v_rhs)) => {
+            let vf_lhs = Float::with_val(precision, v_lhs);
+            float_op(vf_lhs, v_rhs, op, precision)
+        }
+
+        (RecordData::Float(v_lhs), RecordData::Rational(v_rhs)) => {
+            let vf_rhs = Float::with_val(precision, v_rhs);
+            float_op(v_lhs, vf_rhs, op, precision)
+        }
+
+        (RecordData::Complex(v_lhs), RecordData::Complex(v_rhs)) => {
+            complex_op(v_lhs, v_rhs, op)
+        }
+
+        // Every other number can be made complex
+        (RecordData::Complex(v_lhs), rhs @ (RecordData::Integer(_) | RecordData::Float(_) | RecordData::Rational(_))) => {
+            complex_op(v_lhs, to_complex(rhs, precision), op)
+        }
+
+        (lhs @ (RecordData::Integer(_) | RecordData::Float(_) | RecordData::Rational(_)), RecordData::Complex(v_rhs)) => {
+            complex_op(to_complex(lhs, precision), v_rhs, op)
+        }
+
+        (RecordData::Nil, _) | (_, RecordData::Nil) => {
+            Err(ExecutionError::InvalidOperation("No valid operation for type Nil"))
+        }
+
+        (RecordData::Bool(_), _) | (_, RecordData::Bool(_)) => {
+            Err(ExecutionError::InvalidOperation("No valid operation for type Bool"))
+        }
+
+        (RecordData::String(v_lhs), RecordData::String(v_rhs)) => {
+            string_op(v_lhs, v_rhs, op)
+        }
+
+        // A number added to a string is treated as a string
+        (RecordData::String(v_lhs), rhs @ RecordData::Integer(_)) |
+        (RecordData::String(v_lhs), rhs @ RecordData::Float(_)) |
+        (RecordData::String(v_lhs), rhs @ RecordData::Rational(_)) |
+        (RecordData::String(v_lhs), rhs @ RecordData::Complex(_)) => {
+
+            if op != Opcode::Add {
+                return Err(ExecutionError::InvalidOperation("Only '+' can combine a string and a number"));
+            }
+            string_op(v_lhs, number_string(&rhs), op)
+        }
+
+        (lhs @ RecordData::Integer(_), RecordData::String(v_rhs)) |
+        (lhs @ RecordData::Float(_), RecordData::String(v_rhs)) |
+        (lhs @ RecordData::Rational(_), RecordData::String(v_rhs)) |
+        (lhs @ RecordData::Complex(_), RecordData::String(v_rhs)) => {
+
+            if op != Opcode::Add {
+                return Err(ExecutionError::InvalidOperation("Only '+' can combine a string and a number"));
+            }
+            string_op(number_string(&lhs), v_rhs, op)
+        }
+
+        (RecordData::Dict(_), _) | (_, RecordData::Dict(_)) => {
+            Err(ExecutionError::InvalidOperation("No valid operation for type Dictionary"))
+        }
+
+        (RecordData::List(_), _) | (_, RecordData::List(_)) => {
+            Err(ExecutionError::InvalidOperation("No valid operation for type List"))
+        }
+
+        (RecordData::Function(_), _) | (_, RecordData::Function(_)) => {
+            Err(ExecutionError::InvalidOperation("No valid operation for type Function"))
+        }
+    }
+}
+
+/// String representation of a number used when it is combined with a string
+fn number_string(value: &RecordData) -> String {
+
+    match value {
+        RecordData::Integer(v) => { v.to_string_radix(RADIX) }
+        RecordData::Float(v)   => { v.to_string_radix(RADIX, Some(v.prec() as usize)) }
+        RecordData::Rational(v) => { v.to_string_radix(RADIX) }
+        RecordData::Complex(v)  => { complex_string(v) }
+        _ => { String::new() }
+    }
+}
+
+/// Perform a unary operation
+pub(crate) fn unary(item: RecordData, op: UnaryOpcode) -> Result<RecordData, ExecutionError> {
+
+    // Not works on the truthiness of any type
+    if op == UnaryOpcode::Negate {
+        return Ok(RecordData::Bool(!item.is_truthy()));
+    }
+
+    match item {
+
+        RecordData::Integer(v) => {
+            Ok(unary_integer(v, op))
+        }
+
+        // Floats and rationals can be negated as they are, anything else needs a whole number
+        RecordData::Float(v) if op == UnaryOpcode::Minus => {
+            Ok(RecordData::Float(-v))
+        }
+
+        RecordData::Rational(v) if op == UnaryOpcode::Minus => {
+            Ok(RecordData::Rational(-v))
+        }
+
+        RecordData::Rational(_) => {
+            Err(ExecutionError::InvalidOperation("Attempted bitwise operation on rational type"))
+        }
+
+        RecordData::Complex(v) if op == UnaryOpcode::Minus => {
+            Ok(RecordData::Complex(-v))
+        }
+
+        RecordData::Complex(_) => {
+            Err(ExecutionError::InvalidOperation("Attempted bitwise operation on complex type"))
+        }
+        
+        RecordData::Float(v) => {
+
+            match v.to_integer() {
+                Some(i) => {
+                    Ok(unary_integer(i, op))
+                }
+                None    => Err(ExecutionError::ConversionFailure(
+                    "Converting float to integer".to_string(),
+                    "Required for unary operation".to_string()
+                ))
+            }
+        }
+
+        RecordData::Bool(_) => {
+            Err(ExecutionError::InvalidOperation("Attempted unary operation on bool type"))
+        }
+
+        RecordData::Nil => {
+            Err(ExecutionError::InvalidOperation("Attempted unary operation on nil"))
+        }
+
+        RecordData::String(_) => {
+            Err(ExecutionError::InvalidOperation("Attempted unary operation on string type"))
+        }
+
+        RecordData::Dict(_) => {
+            Err(ExecutionError::InvalidOperation("Attempted unary operation on dictionary type"))
+        }
+
+        RecordData::List(_) => {
+            Err(ExecutionError::InvalidOperation("Attempted unary operation on list type"))
+        }
+
+        RecordData::Function(_) => {
+            Err(ExecutionError::InvalidOperation("Attempted unary operation on function type"))
+        }
+    }
+}
+
+/// Perform a unary operation on an integer
+fn unary_integer(item: Integer, op: UnaryOpcode) -> RecordData {
+
+    match op {
+        UnaryOpcode::BwNot => {
+            RecordData::Integer( !item )
+        }
+
+        UnaryOpcode::Negate => {
+            RecordData::Bool( item == 0 )
+        }
+
+        UnaryOpcode::Minus => {
+            RecordData::Integer( -item )
+        }
+    }
+}
+
+/// Execute a string operation
+fn string_op(lhs: String, rhs: String, op: Opcode) -> Result<RecordData, ExecutionError> {
+    
+    match op {
+        Opcode::Add => {
+            Ok(RecordData::String( lhs + rhs.as_str() ))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Invalid operation for type String"))
+        }
+    }
+}
+
+/// Execute an integer operation
+fn integer_op(lhs: Integer, rhs: Integer, op: Opcode) -> Result<RecordData, ExecutionError> {
+
+    match op {
+        Opcode::Mul => {
+
+            // The product has at most as many bits as both sides together
+            if (lhs.significant_bits() as u64) + (rhs.significant_bits() as u64) > MAX_INTEGER_BITS {
+                return Err(ExecutionError::Overflow("mul"));
+            }
+            Ok(RecordData::Integer(lhs * rhs))
+        }
+        Opcode::Div => {
+
+            if rhs == 0 {
+                return Err(ExecutionError::DivisionByZero);
+            }
+            Ok(RecordData::Integer(lhs / rhs))
+        }
+        Opcode::Add => {
+            Ok(RecordData::Integer(lhs + rhs))
+        }
+        Opcode::Sub => {
+            Ok(RecordData::Integer(lhs - rhs))
+        }
+        Opcode::Pow => {
+
+            if rhs < 0 {
+                return Err(ExecutionError::NegativeExponent);
+            }
+
+            // Anything raised to zero is one, zero included
+            if rhs == 0 {
+                return Ok(RecordData::Integer(Integer::from(1)));
+            }
+
+            // 0, 1 and -1 can be raised to anything else without growing, so only the 
+            // parity of the exponent matters for them
+            if lhs.significant_bits() <= 1 {
+                let parity = if rhs.is_odd() { 1 } else { 2 };
+                return Ok(RecordData::Integer(lhs.pow(parity)));
+            }
+
+            // Make sure the result would fit before asking rug to build it
+            let rhs_converted = match u32::try_from(&rhs) {
+                Ok(r) if (lhs.significant_bits() as u64) * (r as u64) <= MAX_INTEGER_BITS => { r }
+                _ => {
+                    return Err(ExecutionError::Overflow("pow"));
+                }
+            };
+
+            Ok(RecordData::Integer(lhs.pow(rhs_converted)))
+        }
+
+        Opcode::Mod => {
+
+            if rhs == 0 {
+                return Err(ExecutionError::DivisionByZero);
+            }
+            Ok(RecordData::Integer(lhs % rhs))
+        }
+
+        Opcode::Lsh => {
+
+            let rhs_converted = match u32::try_from(&rhs) {
+                Ok(r) => { r }
+                Err(_) => {
+                    return Err(ExecutionError::ShiftOutOfRange(rhs));
+                }
+            };
+
+            if (lhs.significant_bits() as u64) + (rhs_converted as u64) > MAX_INTEGER_BITS {
+                return Err(ExecutionError::Overflow("lsh"));
+            }
+
+            Ok(RecordData::Integer(lhs << rhs_converted))
+        }
+
+        Opcode::Rsh => {
+
+            if rhs < 0 {
+                return Err(ExecutionError::ShiftOutOfRange(rhs));
+            }
+
+            // Shifting right by more bits than any value can hold leaves 0, or -1 for a negative
+            // value, so the amount can be held to the largest shift rug takes
+            let rhs_converted = u32::try_from(&rhs).unwrap_or(u32::MAX);
+
+            Ok(RecordData::Integer(lhs >> rhs_converted))
+        }
+
+        Opcode::BwXor => {
+            Ok(RecordData::Integer(lhs ^ rhs))
+        }
+
+        Opcode::BwOr => {
+            Ok(RecordData::Integer(lhs | rhs))
+        }
+
+        Opcode::BwAnd => {
+            Ok(RecordData::Integer(lhs & rhs))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Invalid operation for type Integer"))
+        }
+    }
+}
+
+/// Execute a float operation
+fn float_op(lhs: Float, rhs: Float, op: Opcode, precision: u32) -> Result<RecordData, ExecutionError>  {
+    
+    match op {
+        Opcode::Mul => {
+            Ok(RecordData::Float(lhs * rhs))
+        }
+        Opcode::Div => {
+            Ok(RecordData::Float(lhs / rhs))
+        }
+        Opcode::Add => {
+            Ok(RecordData::Float(lhs + rhs))
+        }
+        Opcode::Sub => {
+            Ok(RecordData::Float(lhs - rhs))
+        }
+        Opcode::Pow => {
+            Ok(RecordData::Float(lhs.pow(rhs)))
+        }
+
+        Opcode::Mod => {
+            Ok(RecordData::Float(lhs % rhs))
+        }
+
+        // Bitwise operations only make sense on whole numbers, so they are 
+        // done as integers and brought back into float
+        Opcode::Lsh | Opcode::Rsh | Opcode::BwXor | Opcode::BwOr | Opcode::BwAnd => {
+
+            let lhs = float_to_integer(&lhs)?;
+            let rhs = float_to_integer(&rhs)?;
+
+            match integer_op(lhs, rhs, op)? {
+                RecordData::Integer(result) => { Ok(RecordData::Float(Float::with_val(precision, result))) }
+                result => { Ok(result) }
+            }
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Invalid operation for type Float"))
+        }
+    }
+}
+
+/// Execute a rational operation
+fn rational_op(lhs: Rational, rhs: Rational, op: Opcode, precision: u32) -> Result<RecordData, ExecutionError> {
+
+    match op {
+        Opcode::Mul => {
+            Ok(RecordData::Rational(lhs * rhs))
+        }
+        Opcode::Div => {
+
+            if rhs == 0 {
+                return Err(ExecutionError::DivisionByZero);
+            }
+            Ok(RecordData::Rational(lhs / rhs))
+        }
+        Opcode::Add => {
+            Ok(RecordData::Rational(lhs + rhs))
+        }
+        Opcode::Sub => {
+            Ok(RecordData::Rational(lhs - rhs))
+        }
+        // Only whole exponents keep the result exact, anything else is done as a float
+        Opcode::Pow => {
+
+            if *rhs.denom() != 1 {
+                let lhs = Float::with_val(precision, lhs);
+                let rhs = Float::with_val(precision, rhs);
+                return Ok(RecordData::Float(lhs.pow(rhs)));
+            }
+
+            let exponent = rhs.numer().clone();
+
+            if lhs == 0 && exponent < 0 {
+                return Err(ExecutionError::DivisionByZero);
+            }
+
+            // Raise the numerator and denominator on their own so they get the same overflow checks as integers
+            let (numer, denom) = lhs.into_numer_denom();
+            let magnitude = exponent.clone().abs();
+
+            let numer = match integer_op(numer, magnitude.clone(), Opcode::Pow)? {
+                RecordData::Integer(numer) => { numer }
+                _ => { return Err(ExecutionError::StackError); }
+            };
+
+            let denom = match integer_op(denom, magnitude, Opcode::Pow)? {
+                RecordData::Integer(denom) => { denom }
+                _ => { return Err(ExecutionError::StackError); }
+            };
+
+            let result = Rational::from((numer, denom));
+
+            if exponent < 0 {
+                return Ok(RecordData::Rational(result.recip()));
+            }
+            Ok(RecordData::Rational(result))
+        }
+
+        // The remainder left once the quotient is rounded toward zero, matching integers
+        Opcode::Mod => {
+
+            if rhs == 0 {
+                return Err(ExecutionError::DivisionByZero);
+            }
+
+            let quotient = Rational::from(Integer::from((lhs.clone() / &rhs).trunc_ref()));
+            Ok(RecordData::Rational(lhs - rhs * quotient))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Invalid operation for type Rational"))
+        }
+    }
+}
+
+/// Make a real number complex
+fn to_complex(item: RecordData, precision: u32) -> Complex {
+
+    match item {
+        RecordData::Integer(v)  => { Complex::with_val(precision, v) }
+        RecordData::Float(v)    => { Complex::with_val(precision, v) }
+        RecordData::Rational(v) => { Complex::with_val(precision, v) }
+        RecordData::Complex(v)  => { v }
+        _ => { Complex::new(precision) }
+    }
+}
+
+/// Execute a complex operation
+fn complex_op(lhs: Complex, rhs: Complex, op: Opcode) -> Result<RecordData, ExecutionError> {
+
+    match op {
+        Opcode::Mul => {
+            Ok(RecordData::Complex(lhs * rhs))
+        }
+        Opcode::Div => {
+
+            if rhs.real().is_zero() && rhs.imag().is_zero() {
+                return Err(ExecutionError::DivisionByZero);
+            }
+            Ok(RecordData::Complex(lhs / rhs))
+        }
+        Opcode::Add => {
+            Ok(RecordData::Complex(lhs + rhs))
+        }
+        Opcode::Sub => {
+            Ok(RecordData::Complex(lhs - rhs))
+        }
+        Opcode::Pow => {
+            Ok(RecordData::Complex(lhs.pow(rhs)))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Invalid operation for type Complex"))
+        }
+    }
+}
+
+/// Get a precision, in bits, out of an item. Like integers, floats are kept from growing past what could be allocated
+pub(crate) fn precision_bits(item: &RecordData) -> Result<u32, ExecutionError> {
+
+    match item {
+        RecordData::Integer(bits) if *bits >= 1 && *bits <= MAX_INTEGER_BITS => {
+            Ok(bits.to_u32().unwrap_or(1))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Precision must be an integer number of bits between 1 and 2^26"))
+        }
+    }
+}
+
+/// The '#precision' built in. Given no arguments it hands back the current precision, 
+/// otherwise it changes the precision that floats are read in and promoted at
+pub(crate) fn precision(current: &mut u32, args: Vec<RecordData>) -> Result<Option<RecordData>, ExecutionError> {
+
+    match args.as_slice() {
+        [] => {
+            Ok(Some(RecordData::Integer(Integer::from(*current))))
+        }
+
+        [bits] => {
+            *current = precision_bits(bits)?;
+            Ok(None)
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidParameters)
+        }
+    }
+}
+
+/// Read in the digits of a float at the given precision
+pub(crate) fn parse_float(digits: &str, precision: u32) -> Result<Float, ExecutionError> {
+
+    match Float::parse(digits) {
+        Ok(parsed) => { Ok(Float::with_val(precision, parsed)) }
+        Err(_) => {
+            Err(ExecutionError::ConversionFailure(digits.to_string(), "Read float".to_string()))
+        }
+    }
+}
+
+/// Read in the digits of an imaginary number at the given precision
+pub(crate) fn parse_imaginary(digits: &str, precision: u32) -> Result<Complex, ExecutionError> {
+
+    let imaginary = parse_float(digits, precision)?;
+    Ok(Complex::with_val(precision, (0, imaginary)))
+}
+
+/// Convert a float to an integer, NaN and infinity have no integer value
+pub(crate) fn float_to_integer(value: &Float) -> Result<Integer, ExecutionError> {
+
+    match value.to_integer() {
+        Some(i) => { Ok(i) }
+        None    => { Err(ExecutionError::NotFinite(value.to_string())) }
+    }
+}
+
+/// Write out an item the way a format spec from an interpolated string asks for
+pub(crate) fn format(item: &RecordData, spec: &FormatSpec) -> Result<String, ExecutionError> {
+
+    let numeric = matches!(item, RecordData::Integer(_) | RecordData::Float(_) | RecordData::Rational(_) | RecordData::Complex(_));
+
+    if spec.radix.is_some() && !matches!(item, RecordData::Integer(_)) {
+        return Err(ExecutionError::InvalidOperation("Only integers can be formatted in another radix"));
+    }
+
+    let written = match (item, spec.precision) {
+
+        (RecordData::Integer(v), _) if spec.radix.is_some() => {
+
+            let digits = v.to_string_radix(spec.radix.unwrap_or(RADIX));
+
+            if spec.uppercase { digits.to_uppercase() } else { digits }
+        }
+
+        (RecordData::Integer(v), Some(places))  => { fixed(&Rational::from(v), places)? }
+        (RecordData::Rational(v), Some(places)) => { fixed(v, places)? }
+
+        // NaN and infinity have no digits to round
+        (RecordData::Float(v), Some(places)) => {
+            match v.to_rational() {
+                Some(exact) => { fixed(&exact, places)? }
+                None => { v.to_string() }
+            }
+        }
+
+        (RecordData::Complex(v), Some(places)) => {
+
+            let real = format(&RecordData::Float(v.real().clone()), &FormatSpec { precision: Some(places), ..FormatSpec::default() })?;
+            let imag = format(&RecordData::Float(v.imag().clone()), &FormatSpec { precision: Some(places), ..FormatSpec::default() })?;
+
+            if imag.starts_with('-') {
+                format!("{}{}i", real, imag)
+            } else {
+                format!("{}+{}i", real, imag)
+            }
+        }
+
+        // Unlike a float turned into a string, only as many digits as are needed to read it back are written
+        (RecordData::Float(v), None) => { v.to_string_radix(RADIX, None) }
+
+        (RecordData::String(v), Some(places)) => { v.chars().take(places).collect() }
+        (RecordData::String(v), None) => { v.clone() }
+
+        (item, _) => {
+            match item.clone().to_string() {
+                Some(RecordData::String(v)) => { v }
+                _ => {
+                    return Err(ExecutionError::ConversionFailure("format".to_string(), "Represent item as string".to_string()));
+                }
+            }
+        }
+    };
+
+    // Padding is held to the same size as a repeated string, with room for fills of up to four bytes
+    if let Some(width) = spec.width {
+        if (width as u64).saturating_mul(4 * 8) > MAX_INTEGER_BITS {
+            return Err(ExecutionError::Overflow("format"));
+        }
+    }
+
+    let length = written.chars().count();
+
+    let width = match spec.width {
+        Some(width) if width > length => { width }
+        _ => { return Ok(written); }
+    };
+
+    let padding = width - length;
+
+    // Zeros go between the sign and the digits
+    if spec.zero && numeric && spec.align.is_none() {
+
+        let (sign, digits) = match written.strip_prefix('-') {
+            Some(digits) => { ("-", digits) }
+            None => { ("", written.as_str()) }
+        };
+        return Ok(format!("{}{}{}", sign, "0".repeat(padding), digits));
+    }
+
+    let fill = |count: usize| spec.fill.to_string().repeat(count);
+
+    let default = if numeric { Alignment::Right } else { Alignment::Left };
+
+    match spec.align.unwrap_or(default) {
+        Alignment::Left   => { Ok(format!("{}{}", written, fill(padding))) }
+        Alignment::Right  => { Ok(format!("{}{}", fill(padding), written)) }
+        Alignment::Center => { Ok(format!("{}{}{}", fill(padding / 2), written, fill(padding - padding / 2))) }
+    }
+}
+
+/// Write out an exact number with a fixed amount of digits after the decimal point, rounding half away from zero
+fn fixed(value: &Rational, places: usize) -> Result<String, ExecutionError> {
+
+    // Each decimal place takes a little over three bits
+    match u32::try_from(places) {
+        Ok(p) if (p as u64) * 4 <= MAX_INTEGER_BITS => { }
+        _ => { return Err(ExecutionError::Overflow("format")); }
+    }
+
+    let scale = Integer::from(10).pow(places as u32);
+    let scaled = (value.clone() * scale).round().into_numer_denom().0;
+
+    let negative = scaled < 0;
+    let mut digits = scaled.abs().to_string_radix(RADIX);
+
+    // Make sure there is at least a zero before the decimal point
+    if digits.len() <= places {
+        digits = format!("{}{}", "0".repeat(places + 1 - digits.len()), digits);
+    }
+
+    let sign = if negative { "-" } else { "" };
+
+    if places == 0 {
+        return Ok(format!("{}{}", sign, digits));
+    }
+
+    let (whole, fraction) = digits.split_at(digits.len() - places);
+    Ok(format!("{}{}.{}", sign, whole, fraction))
+}
+
+/// Call a method on an item. Methods that convert in place (to_int, to_float, ...) and the 
+/// list methods that modify the list change the item itself, so it is handed over as a record
+/// that may be shared with a variable. Anything the method returns is given back
+pub(crate) fn call_method(item: &Rc<RefCell<RecordData>>, method: &str, mut args: Vec<RecordData>, precision: u32) -> Result<Option<RecordData>, ExecutionError> {
+
+    // Strings have a library of their own
+    let string = match &*item.borrow() {
+        RecordData::String(s) if strings::is_method(method) => { Some(s.clone()) }
+        _ => { None }
+    };
+
+    if let Some(string) = string {
+        return strings::call_method(&string, method, args).map(Some);
+    }
+
+    // As do dictionaries
+    if matches!(&*item.borrow(), RecordData::Dict(_)) && DICTIONARY_METHODS.contains(&method) {
+        return dictionary_method(item, method, args);
+    }
+
+    match method {
+
+        "copy" => {
+
+            expect_params(&args, 0)?;
+
+            match &*item.borrow() {
+                RecordData::Dict(dictionary) => { Ok(Some(RecordData::Dict(dictionary.copy()))) }
+                RecordData::List(list)       => { Ok(Some(RecordData::List(list.copy()))) }
+                other                        => { Ok(Some(other.get_value())) }
+            }
+        }
+
+        "deep_copy" => {
+            expect_params(&args, 0)?;
+            return Ok(Some(item.borrow().deep_copy()));
+        }
+
+        "as_string" => {
+            match item.borrow().get_value().to_string() {
+                Some(v) => { Ok(Some(v)) }
+                None    => { Err(ExecutionError::ConversionFailure(method.to_string(), "Represent item as string".to_string())) }
+            }
+        }
+
+        "as_int" => {
+            match item.borrow().get_value().to_int() {
+                Some(v) => { Ok(Some(v)) }
+                None    => { Err(ExecutionError::ConversionFailure(method.to_string(), "Represent item as int".to_string())) }
+            }
+        }
+
+        "as_float" => {
+            match item.borrow().get_value().to_float(precision) {
+                Some(v) => { Ok(Some(v)) }
+                None    => { Err(ExecutionError::ConversionFailure(method.to_string(), "Represent item as float".to_string())) }
+            }
+        }
+
+        "to_string" => {
+            let converted = item.borrow().get_value().to_string();
+            match converted {
+                Some(v) => { item.borrow_mut().update_value(v); }
+                None    => { return Err(ExecutionError::ConversionFailure(method.to_string(), "Convert item to string".to_string())); }
+            }
+            Ok(None)
+        }
+
+        "to_int" => {
+            let converted = item.borrow().get_value().to_int();
+            match converted {
+                Some(v) => { item.borrow_mut().update_value(v); }
+                None    => { return Err(ExecutionError::ConversionFailure(method.to_string(), "Convert item to int".to_string())); }
+            }
+            Ok(None)
+        }
+
+        "to_float" => {
+            let converted = item.borrow().get_value().to_float(precision);
+            match converted {
+                Some(v) => { item.borrow_mut().update_value(v); }
+                None    => { return Err(ExecutionError::ConversionFailure(method.to_string(), "Convert item to float".to_string())); }
+            }
+            Ok(None)
+        }
+
+        "with_precision" => {
+
+            expect_params(&args, 1)?;
+            let bits = precision_bits(&args[0])?;
+
+            match item.borrow().get_value().set_precision(bits) {
+                Some(v) => { Ok(Some(v)) }
+                None    => { Err(ExecutionError::ConversionFailure(method.to_string(), "Represent item with precision".to_string())) }
+            }
+        }
+
+        "set_precision" => {
+
+            expect_params(&args, 1)?;
+            let bits = precision_bits(&args[0])?;
+
+            let converted = item.borrow().get_value().set_precision(bits);
+            match converted {
+                Some(v) => { item.borrow_mut().update_value(v); }
+                None    => { return Err(ExecutionError::ConversionFailure(method.to_string(), "Set precision of item".to_string())); }
+            }
+            Ok(None)
+        }
+
+        "len" => {
+
+            expect_params(&args, 0)?;
+
+            match &*item.borrow() {
+                RecordData::List(list) => { Ok(Some(RecordData::Integer(Integer::from(list.len())))) }
+                _ => {
+                    Err(ExecutionError::InvalidOperation("Type does not contain method '.len()' "))
+                }
+            }
+        }
+
+        "re" | "im" | "abs" | "arg" | "conj" => {
+
+            expect_params(&args, 0)?;
+
+            let complex = match &*item.borrow() {
+                RecordData::Complex(c) => { c.clone() }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Only complex numbers have real and imaginary parts"));
+                }
+            };
+
+            let result = match method {
+                "re"   => { RecordData::Float(complex.real().clone()) }
+                "im"   => { RecordData::Float(complex.imag().clone()) }
+                "abs"  => { RecordData::Float(complex.abs().real().clone()) }
+                "arg"  => { RecordData::Float(complex.arg().real().clone()) }
+                _      => { RecordData::Complex(complex.conj()) }
+            };
+            Ok(Some(result))
+        }
+
+        "numer" | "denom" => {
+
+            expect_params(&args, 0)?;
+
+            let rational = match &*item.borrow() {
+                RecordData::Rational(r) => { r.clone() }
+                RecordData::Integer(i)  => { Rational::from(i) }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Only rationals and integers have a numerator and denominator"));
+                }
+            };
+
+            if method == "numer" {
+                return Ok(Some(RecordData::Integer(rational.numer().clone())));
+            }
+            Ok(Some(RecordData::Integer(rational.denom().clone())))
+        }
+
+        "push" => {
+
+            expect_params(&args, 1)?;
+
+            if args[0].holds(&item.borrow()) {
+                return Err(nested_in_itself());
+            }
+
+            match &mut *item.borrow_mut() {
+                RecordData::List(list) => { list.push(args.remove(0)); }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Non-list type does not contain method '.push(V)' "));
+                }
+            }
+            Ok(None)
+        }
+
+        "pop" => {
+
+            expect_params(&args, 0)?;
+
+            let popped = match &mut *item.borrow_mut() {
+                RecordData::List(list) => { list.pop() }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Non-list type does not contain method '.pop()' "));
+                }
+            };
+
+            match popped {
+                Some(value) => { Ok(Some(value)) }
+                None => { Err(ExecutionError::IndexError) }
+            }
+        }
+
+        "insert" => {
+
+            expect_params(&args, 2)?;
+
+            let value = args.remove(1);
+
+            if value.holds(&item.borrow()) {
+                return Err(nested_in_itself());
+            }
+
+            let index = match args.remove(0) {
+                RecordData::Integer(i) => { i }
+                _ => { return Err(ExecutionError::InvalidOperation("Parameter expected integer")); }
+            };
+
+            let inserted = match &mut *item.borrow_mut() {
+                RecordData::List(list) => { list.insert(&index, value) }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Non-list type does not contain method '.insert(N, V)' "));
+                }
+            };
+
+            if !inserted {
+                return Err(ExecutionError::IndexError);
+            }
+            Ok(None)
+        }
+
+        // Any items can be sorted, those of different types are placed in the order of their types
+        "sort" => {
+
+            expect_params(&args, 0)?;
+
+            match &mut *item.borrow_mut() {
+                RecordData::List(list) => { list.sort_by(compare::total); }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Non-list type does not contain method '.sort()' "));
+                }
+            }
+            Ok(None)
+        }
+
+        "remove" => {
+
+            expect_params(&args, 1)?;
+
+            let index = match args.remove(0) {
+                RecordData::Integer(i) => { i }
+                _ => { return Err(ExecutionError::InvalidOperation("Parameter expected integer")); }
+            };
+
+            let removed = match &mut *item.borrow_mut() {
+                RecordData::List(list) => { list.remove(&index) }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Non-list type does not contain method '.remove(N)' "));
+                }
+            };
+
+            match removed {
+                Some(value) => { Ok(Some(value)) }
+                None => { Err(ExecutionError::IndexError) }
+            }
+        }
+
+        _ => {
+
+            Err(ExecutionError::UnknownVariableMethod(".", method.to_string()))
+        }
+    }
+}
+
+/// Methods that can be called on a dictionary
+const DICTIONARY_METHODS: [&str; 9] = [
+    "keys", "values", "items", "len", "contains", "remove", "get", "merge", "clear"
+];
+
+/// Call a method on a dictionary. Dictionaries and lists read out of it are shared with it,
+/// remove, merge and clear change the dictionary itself
+fn dictionary_method(item: &Rc<RefCell<RecordData>>, method: &str, mut args: Vec<RecordData>) -> Result<Option<RecordData>, ExecutionError> {
+
+    // The dictionary being merged in may hold this very item, so look for a cycle
+    // before the item is borrowed for writing
+    if method == "merge" && args.len() == 1 {
+
+        let holder = item.borrow().get_value();
+
+        if let RecordData::Dict(other) = &args[0] {
+            for (_, value) in other.entries() {
+                if value.borrow().holds(&holder) {
+                    return Err(nested_in_itself());
+                }
+            }
+        }
+    }
+
+    let mut borrowed = item.borrow_mut();
+
+    let dictionary = match &mut *borrowed {
+        RecordData::Dict(dictionary) => { dictionary }
+        _ => { return Err(ExecutionError::InvalidOperation("Expected a dictionary")); }
+    };
+
+    match method {
+
+        "keys" | "values" | "items" => {
+
+            expect_params(&args, 0)?;
+
+            let mut items = List::new();
+
+            for (key, value) in dictionary.entries() {
+
+                let key = key.to_record();
+                let value = value.borrow().get_value();
+
+                match method {
+                    "keys"   => { items.push(key); }
+                    "values" => { items.push(value); }
+                    _        => { items.push(pair(key, value)); }
+                }
+            }
+            Ok(Some(RecordData::List(items)))
+        }
+
+        "len" => {
+            expect_params(&args, 0)?;
+            Ok(Some(RecordData::Integer(Integer::from(dictionary.len()))))
+        }
+
+        "contains" => {
+            expect_params(&args, 1)?;
+            let key = Key::from_record(&args[0])?;
+            Ok(Some(RecordData::Bool(dictionary.get(&key).is_some())))
+        }
+
+        "remove" => {
+
+            expect_params(&args, 1)?;
+            let key = Key::from_record(&args[0])?;
+
+            match dictionary.get(&key) {
+                Some(removed) => {
+                    dictionary.remove(&key);
+                    return Ok(Some(removed.borrow().get_value()));
+                }
+                None => { Err(ExecutionError::MissingKey(key.to_string())) }
+            }
+        }
+
+        // The default is optional, without one a missing key gives nil
+        "get" => {
+
+            let default = match args.len() {
+                1 => { RecordData::Nil }
+                2 => { args.remove(1) }
+                _ => { return Err(ExecutionError::InvalidParameters); }
+            };
+
+            let key = Key::from_record(&args[0])?;
+
+            match dictionary.get(&key) {
+                Some(value) => { return Ok(Some(value.borrow().get_value())); }
+                None => { Ok(Some(default)) }
+            }
+        }
+
+        // Keys of the other dictionary replace any that are already here
+        "merge" => {
+
+            expect_params(&args, 1)?;
+
+            let other = match &args[0] {
+                RecordData::Dict(other) => { other }
+                _ => { return Err(ExecutionError::InvalidOperation("Only a dictionary can be merged into a dictionary")); }
+            };
+
+            for (key, value) in other.entries() {
+                dictionary.set(&key, value.borrow().get_value());
+            }
+            Ok(None)
+        }
+
+        "clear" => {
+            expect_params(&args, 0)?;
+            dictionary.clear();
+            Ok(None)
+        }
+
+        _ => {
+            Err(ExecutionError::UnknownVariableMethod(".", method.to_string()))
+        }
+    }
+}
+
+/// A key and value as a list of two items
+fn pair(key: RecordData, value: RecordData) -> RecordData {
+
+    let mut pair = List::new();
+    pair.push(key);
+    pair.push(value);
+    RecordData::List(pair)
+}
+
+/// The items a for loop walks over. Everything but a range is gathered up front, so changing 
+/// what is being looped over from inside of the loop doesn't change what the loop visits
+#[derive(Debug, Clone)]
+pub(crate) enum Iteration {
+    Items(std::vec::IntoIter<RecordData>),
+
+    // Ranges are counted as they go, they can be far too large to gather
+    Range { next: Integer, last: Integer }
+}
+
+impl Iterator for Iteration {
+    type Item = RecordData;
+
+    fn next(&mut self) -> Option<RecordData> {
+        match self {
+            Iteration::Items(items) => { items.next() }
+            Iteration::Range { next, last } => {
+
+                if *next > *last {
+                    return None;
+                }
+
+                let current = next.clone();
+                *next += 1;
+                Some(RecordData::Integer(current))
+            }
+        }
+    }
+}
+
+/// Start iterating over an item. Lists give their items, strings their characters and dictionaries their 
+/// keys in order. When a loop takes a key and value a dictionary gives its items instead
+pub(crate) fn iterate(item: RecordData, pairs: bool) -> Result<Iteration, ExecutionError> {
+
+    let items: Vec<RecordData> = match item {
+        RecordData::List(list) => {
+            list.iter().map(|record| record.borrow().get_value()).collect()
+        }
+
+        RecordData::String(s) => {
+            s.chars().map(|c| RecordData::String(c.to_string())).collect()
+        }
+
+        RecordData::Dict(dictionary) => {
+            dictionary.entries().into_iter().map(|(key, value)| {
+
+                let key = key.to_record();
+
+                if pairs {
+                    pair(key, value.borrow().get_value())
+                } else {
+                    key
+                }
+            }).collect()
+        }
+
+        _ => {
+            return Err(ExecutionError::InvalidOperation("Attempted to iterate over a type that can not be iterated"));
+        }
+    };
+
+    Ok(Iteration::Items(items.into_iter()))
+}
+
+/// Start iterating over a range of integers
+pub(crate) fn range(start: RecordData, end: RecordData, inclusive: bool) -> Result<Iteration, ExecutionError> {
+
+    match (start, end) {
+        (RecordData::Integer(start), RecordData::Integer(end)) => {
+
+            let last = if inclusive { end } else { end - 1 };
+            Ok(Iteration::Range { next: start, last })
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Range bounds must be integers"))
+        }
+    }
+}
+
+/// Split an item into the key and value of a two variable for loop
+pub(crate) fn unpack(item: RecordData) -> Result<(RecordData, RecordData), ExecutionError> {
+
+    match item {
+        RecordData::List(list) if list.len() == 2 => {
+
+            let mut values = list.iter().map(|record| record.borrow().get_value());
+
+            match (values.next(), values.next()) {
+                (Some(key), Some(value)) => { Ok((key, value)) }
+                _ => { Err(ExecutionError::StackError) }
+            }
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Loops over two variables need pairs of items to unpack"))
+        }
+    }
+}
+
+/// Ensure the expected amount of parameters were given to a method
+pub(crate) fn expect_params(args: &[RecordData], expected: usize) -> Result<(), ExecutionError> {
+
+    if args.len() != expected {
+        return Err(ExecutionError::InvalidParameters);
+    }
+    Ok(())
+}
+
+/// Get an item out of a dictionary or list by its key. Dictionaries are keyed by strings,
+/// integers and bools, and lists by integers. Anything else can't be found
+pub(crate) fn element(container: &Rc<RefCell<RecordData>>, key: &RecordData) -> Result<Rc<RefCell<RecordData>>, ExecutionError> {
+
+    match (&*container.borrow(), key) {
+
+        (RecordData::Dict(dictionary), key) => {
+
+            let key = Key::from_record(key)?;
+
+            match dictionary.get(&key) {
+                Some(val) => { Ok(val) }
+                None => { Err(ExecutionError::MissingKey(key.to_string())) }
+            }
+        }
+
+        (RecordData::List(list), RecordData::Integer(index)) => {
+
+            match list.get(index) {
+                Some(val) => { Ok(val) }
+                None => { Err(ExecutionError::IndexError) }
+            }
+        }
+
+        (RecordData::List(_), _) => {
+            Err(ExecutionError::InvalidOperation("List indexes must be integers"))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidOperation("Only dictionaries and lists can be accessed by key"))
+        }
+    }
+}
+
+/// Get an item out of a dictionary or list like 'element', but a key missing from a dictionary
+/// is first set to the given item
+pub(crate) fn element_or_insert(container: &Rc<RefCell<RecordData>>, key: &RecordData, missing: RecordData) -> Result<Rc<RefCell<RecordData>>, ExecutionError> {
+
+    if let RecordData::Dict(dictionary) = &mut *container.borrow_mut() {
+
+        let key = Key::from_record(key)?;
+
+        if dictionary.get(&key).is_none() {
+            dictionary.set(&key, missing);
+        }
+        return dictionary.get(&key).ok_or(ExecutionError::MissingKey(key.to_string()));
+    }
+
+    element(container, key)
+}
+
+/// Follow the keys of a nested assignment, ie 'a['x']['y'] = 1', and assign the value to the last
+/// of them. The last key is created if the dictionary doesn't have it yet. Keys along the way have
+/// to exist already, unless auto vivify is on, in which case each one that is missing is created
+/// as an empty dictionary
+pub(crate) fn assign(record: Rc<RefCell<RecordData>>, keys: &[RecordData], value: RecordData, auto_vivify: bool) -> Result<(), ExecutionError> {
+
+    let (last, path) = match keys.split_last() {
+        Some(split) => { split }
+        None => { return Err(ExecutionError::StackError); }
+    };
+
+    let mut container = record;
+
+    for key in path {
+
+        container = if auto_vivify {
+            element_or_insert(&container, key, RecordData::Dict(Dictionary::new()))?
+        } else {
+            element(&container, key)?
+        };
+    }
+
+    if value.holds(&container.borrow()) {
+        return Err(nested_in_itself());
+    }
+
+    element_or_insert(&container, last, RecordData::Nil)?.borrow_mut().update_value(value);
+    Ok(())
+}
+
+/// Error for placing a dictionary or list somewhere within itself
+fn nested_in_itself() -> ExecutionError {
+    ExecutionError::InvalidOperation("A dictionary or list can not be placed inside of itself")
+}
+
+/// The '#auto_vivify' built in. Given no arguments it hands back if auto vivify is on,
+/// otherwise it turns it on or off
+pub(crate) fn auto_vivify(current: &mut bool, args: Vec<RecordData>) -> Result<Option<RecordData>, ExecutionError> {
+
+    match args.as_slice() {
+        [] => {
+            Ok(Some(RecordData::Bool(*current)))
+        }
+
+        [RecordData::Bool(enabled)] => {
+            *current = *enabled;
+            Ok(None)
+        }
+
+        [_] => {
+            Err(ExecutionError::InvalidOperation("Auto vivify can only be set to true or false"))
+        }
+
+        _ => {
+            Err(ExecutionError::InvalidParameters)
+        }
+    }
+}
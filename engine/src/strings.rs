@@ -0,0 +1,237 @@
+
+/*
+    The string library. These are the methods that can be called on a string, ie '"a,b".split(",")'.
+
+    Strings are handled as a sequence of unicode scalar values rather than bytes, so indexes,
+    lengths and slices all count characters. None of the methods change the string they are
+    called on, a new item is always handed back
+*/
+
+use rug::Integer;
+
+use crate::types::{ RecordData, List };
+use crate::error::ExecutionError;
+use crate::ops::{ expect_params, MAX_INTEGER_BITS };
+
+/// Every method in the library
+const METHODS: [&str; 15] = [
+    "len", "at", "slice", "find", "contains", "starts_with", "ends_with",
+    "split", "join", "replace", "repeat", "trim", "upper", "lower", "chars"
+];
+
+/// Check if a method is part of the string library
+pub(crate) fn is_method(method: &str) -> bool {
+    METHODS.contains(&method)
+}
+
+/// Call a method from the string library on a string
+pub(crate) fn call_method(string: &str, method: &str, args: Vec<RecordData>) -> Result<RecordData, ExecutionError> {
+
+    match method {
+
+        "len" => {
+            expect_params(&args, 0)?;
+            Ok(RecordData::Integer(Integer::from(string.chars().count())))
+        }
+
+        "at" => {
+
+            expect_params(&args, 1)?;
+            let index = index(&args[0])?;
+
+            match string.chars().nth(index) {
+                Some(c) => { Ok(RecordData::String(c.to_string())) }
+                None    => { Err(ExecutionError::IndexError) }
+            }
+        }
+
+        "slice" => {
+
+            // The end can be left off to slice to the end of the string
+            let length = string.chars().count();
+
+            let (start, end) = match args.as_slice() {
+                [start]      => { (index(start)?, length) }
+                [start, end] => { (index(start)?, index(end)?) }
+                _ => { return Err(ExecutionError::InvalidParameters); }
+            };
+
+            if start > end || end > length {
+                return Err(ExecutionError::IndexError);
+            }
+            Ok(RecordData::String(string.chars().skip(start).take(end - start).collect()))
+        }
+
+        "find" => {
+
+            expect_params(&args, 1)?;
+            let pattern = text(&args[0])?;
+
+            // Nil when the pattern isn't in the string at all
+            match string.find(pattern) {
+                Some(byte) => { Ok(RecordData::Integer(Integer::from(string[..byte].chars().count()))) }
+                None       => { Ok(RecordData::Nil) }
+            }
+        }
+
+        "contains" | "starts_with" | "ends_with" => {
+
+            expect_params(&args, 1)?;
+            let pattern = text(&args[0])?;
+
+            let result = match method {
+                "contains"    => { string.contains(pattern) }
+                "starts_with" => { string.starts_with(pattern) }
+                _             => { string.ends_with(pattern) }
+            };
+            Ok(RecordData::Bool(result))
+        }
+
+        "split" => {
+
+            // Without a separator the string is split on whitespace
+            let parts: Vec<&str> = match args.as_slice() {
+                [] => { string.split_whitespace().collect() }
+                [separator] => {
+
+                    let separator = text(separator)?;
+
+                    if separator.is_empty() {
+                        return Err(ExecutionError::InvalidOperation("Can not split on an empty separator, use '.chars()' instead"));
+                    }
+                    string.split(separator).collect()
+                }
+                _ => { return Err(ExecutionError::InvalidParameters); }
+            };
+
+            Ok(list(parts.into_iter().map(String::from)))
+        }
+
+        "chars" => {
+            expect_params(&args, 0)?;
+            Ok(list(string.chars().map(String::from)))
+        }
+
+        "join" => {
+
+            expect_params(&args, 1)?;
+
+            // The string is placed between each item of the list, anything that can be
+            // represented as a string can be joined
+            let items = match &args[0] {
+                RecordData::List(items) => { items }
+                _ => { return Err(ExecutionError::InvalidOperation("Join expects a list of items")); }
+            };
+
+            let mut parts = Vec::new();
+
+            for item in items.iter() {
+                match item.borrow().get_value().to_string() {
+                    Some(RecordData::String(part)) => { parts.push(part); }
+                    _ => {
+                        return Err(ExecutionError::ConversionFailure(method.to_string(), "Represent item as string".to_string()));
+                    }
+                }
+            }
+            Ok(RecordData::String(parts.join(string)))
+        }
+
+        "replace" => {
+
+            expect_params(&args, 2)?;
+            let from = text(&args[0])?;
+            let to = text(&args[1])?;
+
+            if from.is_empty() {
+                return Err(ExecutionError::InvalidOperation("Can not replace an empty string"));
+            }
+            Ok(RecordData::String(string.replace(from, to)))
+        }
+
+        "repeat" => {
+
+            expect_params(&args, 1)?;
+            let count = match &args[0] {
+                RecordData::Integer(i) if *i < 0 => {
+                    return Err(ExecutionError::InvalidOperation("Can not repeat a string a negative number of times"));
+                }
+                RecordData::Integer(i) => {
+                    match i.to_usize() {
+                        Some(count) => { count }
+                        None        => { return Err(ExecutionError::Overflow("repeat")); }
+                    }
+                }
+                _ => {
+                    return Err(ExecutionError::InvalidOperation("Parameter expected integer"));
+                }
+            };
+
+            // Keep the result from growing past what could be allocated
+            match (string.len() as u64).checked_mul(count as u64) {
+                Some(size) if size <= MAX_INTEGER_BITS / 8 => {
+                    Ok(RecordData::String(string.repeat(count)))
+                }
+                _ => {
+                    Err(ExecutionError::Overflow("repeat"))
+                }
+            }
+        }
+
+        "trim" => {
+            expect_params(&args, 0)?;
+            Ok(RecordData::String(string.trim().to_string()))
+        }
+
+        "upper" => {
+            expect_params(&args, 0)?;
+            Ok(RecordData::String(string.to_uppercase()))
+        }
+
+        "lower" => {
+            expect_params(&args, 0)?;
+            Ok(RecordData::String(string.to_lowercase()))
+        }
+
+        _ => {
+            Err(ExecutionError::UnknownVariableMethod(".", method.to_string()))
+        }
+    }
+}
+
+/// Get a character index or count out of a parameter
+fn index(item: &RecordData) -> Result<usize, ExecutionError> {
+
+    match item {
+        RecordData::Integer(i) => {
+            match i.to_usize() {
+                Some(index) => { Ok(index) }
+                None        => { Err(ExecutionError::IndexError) }
+            }
+        }
+        _ => {
+            Err(ExecutionError::InvalidOperation("Parameter expected integer"))
+        }
+    }
+}
+
+/// Get the text out of a string parameter
+fn text(item: &RecordData) -> Result<&str, ExecutionError> {
+
+    match item {
+        RecordData::String(s) => { Ok(s) }
+        _ => {
+            Err(ExecutionError::InvalidOperation("Parameter expected string"))
+        }
+    }
+}
+
+/// Build a list of strings
+fn list<I: Iterator<Item = String>>(parts: I) -> RecordData {
+
+    let mut list = List::new();
+
+    for part in parts {
+        list.push(RecordData::String(part));
+    }
+    RecordData::List(list)
+}
@@ -1,18 +1,30 @@
 
 use std::{ cell::RefCell, rc::Rc };
-use rug::{Integer, Float, Assign};
+use rug::{Integer, Float, Rational, Complex, Assign};
 use std::collections::HashMap;
+use std::hash::Hash;
+use std::fmt;
+use std::cmp::Ordering;
 
 extern crate micron_ast;
-use micron_ast::{ FLOAT_PRECISION, RADIX };
+use micron_ast::{ Statement, RADIX };
+
+use crate::bytecode::Chunk;
+use crate::value::Key;
 
 /// Record of data
 #[derive(Debug, Clone)]
 pub(crate) enum RecordData {
     Integer(Integer),
     Float(Float),
+    Rational(Rational),
+    Complex(Complex),
+    Bool(bool),
+    Nil,
     String(String),
-    Dict(Dictionary)
+    Dict(Dictionary),
+    List(List),
+    Function(Function)
 }
 
 /// Helper functions for record data
@@ -24,18 +36,86 @@ impl RecordData {
     }
 
     pub(crate) fn get_value(&self) -> RecordData {
-        match &*self {
+        match self {
             RecordData::Integer(v) => RecordData::Integer(v.clone()),
             RecordData::Float(v)   => RecordData::Float(v.clone()),
+            RecordData::Rational(v) => RecordData::Rational(v.clone()),
+            RecordData::Complex(v) => RecordData::Complex(v.clone()),
+            RecordData::Bool(v)    => RecordData::Bool(*v),
+            RecordData::Nil        => RecordData::Nil,
             RecordData::String(v)  => RecordData::String(v.clone()),
-            RecordData::Dict(v)    => RecordData::Dict(v.clone())
+            RecordData::Dict(v)    => RecordData::Dict(v.clone()),
+            RecordData::List(v)    => RecordData::List(v.clone()),
+            RecordData::Function(v) => RecordData::Function(v.clone())
         }
     }
 
-    pub(crate) fn to_string(&mut self) -> Option<RecordData> {
-        match &*self {
+    /// A copy that shares nothing with the original. Dictionaries and lists are copied all 
+    /// the way down rather than only at the top
+    pub(crate) fn deep_copy(&self) -> RecordData {
+        match self {
+            RecordData::Dict(dictionary) => {
+
+                let mut copy = Dictionary::new();
+
+                for (key, record) in dictionary.entries() {
+                    copy.set(&key, record.borrow().deep_copy());
+                }
+                RecordData::Dict(copy)
+            }
+
+            RecordData::List(list) => {
+
+                let mut copy = List::new();
+
+                for record in list.iter() {
+                    copy.push(record.borrow().deep_copy());
+                }
+                RecordData::List(copy)
+            }
+
+            _ => { self.get_value() }
+        }
+    }
+
+    /// Check if placing this record within the given container would put the container inside 
+    /// of itself, either directly or somewhere further down
+    pub(crate) fn holds(&self, container: &RecordData) -> bool {
+
+        match (self, container) {
+            (RecordData::Dict(a), RecordData::Dict(b)) if a.shares(b) => { return true; }
+            (RecordData::List(a), RecordData::List(b)) if a.shares(b) => { return true; }
+            _ => { }
+        }
+
+        match self {
+            RecordData::Dict(dictionary) => { dictionary.entries().iter().any(|(_, record)| record.borrow().holds(container)) }
+            RecordData::List(list)       => { list.iter().any(|record| record.borrow().holds(container)) }
+            _ => { false }
+        }
+    }
+
+    /// Check if the record meets the requirements for 'true' within a conditional.
+    /// Nil, false, zero, NaN and empty containers are false, everything else is true
+    pub(crate) fn is_truthy(&self) -> bool {
+        match self {
+            RecordData::Integer(i)  => { *i != 0 }
+            RecordData::Float(f)    => { !f.is_zero() && !f.is_nan() }
+            RecordData::Rational(r) => { *r != 0 }
+            RecordData::Complex(c)  => { !c.real().is_zero() || !c.imag().is_zero() }
+            RecordData::Bool(b)     => { *b }
+            RecordData::Nil         => { false }
+            RecordData::String(s)   => { !s.is_empty() }
+            RecordData::Dict(d)     => { d.len() > 0 }
+            RecordData::List(l)     => { l.len() > 0 }
+            RecordData::Function(_) => { true }
+        }
+    }
+
+    pub(crate) fn to_string(&self) -> Option<RecordData> {
+        match self {
             RecordData::Integer(v) => {
-                Some(RecordData::String(String::from(v.to_string_radix(RADIX))))
+                Some(RecordData::String(v.to_string_radix(RADIX)))
             }
 
             RecordData::Float(v)   => {
@@ -43,6 +123,24 @@ impl RecordData {
                 Some(RecordData::String(v.to_string_radix(RADIX, Some(v.prec() as usize))))
             }
 
+            RecordData::Rational(v) => {
+
+                Some(RecordData::String(v.to_string_radix(RADIX)))
+            }
+
+            RecordData::Complex(v) => {
+
+                Some(RecordData::String(complex_string(v)))
+            }
+
+            RecordData::Bool(v)    => {
+                Some(RecordData::String(v.to_string()))
+            }
+
+            RecordData::Nil        => {
+                Some(RecordData::String("nil".to_string()))
+            }
+
             RecordData::String(v)  => {
                  Some(RecordData::String(v.clone()))
             }
@@ -51,22 +149,40 @@ impl RecordData {
 
                 Some(RecordData::String(format!("{:?}", v)))
             }
+
+            RecordData::List(v)    => {
+
+                Some(RecordData::String(format!("{:?}", v)))
+            }
+
+            RecordData::Function(v) => {
+
+                Some(RecordData::String(format!("{:?}", v)))
+            }
         }
     }
 
-    pub(crate) fn to_int(&mut self) -> Option<RecordData> {
+    pub(crate) fn to_int(&self) -> Option<RecordData> {
 
-        match &*self {
+        match self {
             RecordData::Integer(v) => {
                 Some(RecordData::Integer(v.clone()))
             }
 
             RecordData::Float(v)   => {
 
-                match v.to_integer() {
-                    Some(v_int) => { Some(RecordData::Integer(v_int)) }
-                    None => None 
-                }
+                v.to_integer().map(RecordData::Integer)
+            }
+
+            // Rounds toward zero, the same way integer division does
+            RecordData::Rational(v) => {
+
+                Some(RecordData::Integer(Integer::from(v.trunc_ref())))
+            }
+
+            RecordData::Bool(v)    => {
+
+                Some(RecordData::Integer(Integer::from(*v as u32)))
             }
 
             RecordData::String(v)  => {
@@ -82,19 +198,19 @@ impl RecordData {
                 Some(RecordData::Integer(Integer::from(i_val.unwrap() as i64)))
             }
 
-            RecordData::Dict(_)    => {
+            RecordData::Nil | RecordData::Complex(_) | RecordData::Dict(_) | RecordData::List(_) | RecordData::Function(_) => {
 
                 None
             }
         }
     }
 
-    pub(crate) fn to_float(&mut self) -> Option<RecordData> {
+    pub(crate) fn to_float(&self, precision: u32) -> Option<RecordData> {
 
-        match &*self {
+        match self {
             RecordData::Integer(v) => {
 
-                let mut f_v = Float::new(FLOAT_PRECISION);
+                let mut f_v = Float::new(precision);
                 f_v.assign(v);
 
                 Some(RecordData::Float( f_v ))
@@ -105,24 +221,32 @@ impl RecordData {
                 Some(RecordData::Float(v.clone()))
             }
 
-            RecordData::String(v)  => {
+            RecordData::Rational(v) => {
 
-                let i_val = v.parse::<f64>();
+                Some(RecordData::Float(Float::with_val(precision, v)))
+            }
 
-                if i_val.is_err() {
-                    return None;
-                }
+            RecordData::Bool(v)    => {
+
+                Some(RecordData::Float(Float::with_val(precision, *v as u32)))
+            }
+
+            RecordData::String(v)  => {
 
-                Some(RecordData::Float(Float::with_val(FLOAT_PRECISION, i_val.unwrap())))
+                match Float::parse(v.trim()) {
+                    Ok(parsed) => { Some(RecordData::Float(Float::with_val(precision, parsed))) }
+                    Err(_)     => { None }
+                }
             }
 
-            RecordData::Dict(_)    => {
+            RecordData::Nil | RecordData::Complex(_) | RecordData::Dict(_) | RecordData::List(_) | RecordData::Function(_) => {
 
                 None
             }
         }
     }
 
+    /// Round a number to the given precision. Whole numbers and fractions become floats
     pub(crate) fn set_precision(&mut self, precision: u32) -> Option<RecordData> {
         match &*self {
             RecordData::Float(v)   => {
@@ -130,7 +254,17 @@ impl RecordData {
                 let mut f_v = Float::new(precision);
                 f_v.assign(v);
 
-                Some(RecordData::Float(v.clone()))
+                Some(RecordData::Float(f_v))
+            }
+
+            RecordData::Complex(v) => {
+
+                Some(RecordData::Complex(Complex::with_val(precision, v)))
+            }
+
+            RecordData::Integer(_) | RecordData::Rational(_) => {
+
+                self.to_float(precision)
             }
 
             _ => {
@@ -140,45 +274,215 @@ impl RecordData {
     }
 }
 
-/// A dictionary of data
-#[derive(Debug, Clone)]
-pub(crate) struct Dictionary {
-    data: HashMap<String, Rc<RefCell<RecordData>>>
+/// String representation of a complex number, written the way it would be in a script
+pub(crate) fn complex_string(value: &Complex) -> String {
+
+    let real = value.real();
+    let imag = value.imag();
+
+    let sign = if imag.is_sign_negative() { "-" } else { "+" };
+
+    format!("{}{}{}i", 
+        real.to_string_radix(RADIX, None), 
+        sign, 
+        Float::with_val(imag.prec(), imag.abs_ref()).to_string_radix(RADIX, None))
+}
+
+/// A dictionary of data. The dictionaries of scripts are keyed by any key, while the 
+/// scopes of the engine use the same structure keyed by variable name.
+/// 
+/// A dictionary is a handle, cloning it gives another handle to the same keys rather than 
+/// new ones. 'copy' and 'deep_copy' are there for when a separate dictionary is wanted
+#[derive(Clone)]
+pub(crate) struct Dictionary<K = Key> {
+    data: Rc<RefCell<HashMap<K, Rc<RefCell<RecordData>>>>>
+}
+
+impl<K: Hash + Eq + Ord + Clone> Dictionary<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: Rc::new(RefCell::new(HashMap::new()))
+        }
+    }
+
+    /// Get a record
+    pub(crate) fn get(&self, key: &K) -> Option<Rc<RefCell<RecordData>>> {
+        self.data.borrow().get(key).cloned()
+    }
+
+    /// Set a record to record data
+    pub(crate) fn set(&mut self, key: &K, value: RecordData) {
+
+        self.data.borrow_mut().insert(key.clone(), Rc::new(RefCell::new(value)));
+    }
+
+    /// Attempt to remove a key
+    pub(crate) fn remove(&mut self, key: &K) -> bool {
+
+        return self.data.borrow_mut().remove(key).is_some();
+    }
+
+    /// Remove every key
+    pub(crate) fn clear(&mut self) {
+        self.data.borrow_mut().clear();
+    }
+
+    /// Number of keys in the dictionary
+    pub(crate) fn len(&self) -> usize {
+        self.data.borrow().len()
+    }
+
+    /// The keys and records ordered by key, so walking a dictionary always visits it the same way.
+    /// The dictionary isn't held on to, so it can be changed while its entries are walked
+    pub(crate) fn entries(&self) -> Vec<(K, Rc<RefCell<RecordData>>)> {
+        let mut entries: Vec<_> = self.data.borrow().iter().map(|(key, record)| (key.clone(), record.clone())).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Check if two handles are to the same dictionary
+    pub(crate) fn shares(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.data, &other.data)
+    }
+
+    /// A new dictionary with the same keys. Dictionaries and lists held by this one are
+    /// shared with the copy rather than copied themselves
+    pub(crate) fn copy(&self) -> Self {
+
+        let mut copy = Self::new();
+
+        for (key, record) in self.entries() {
+            copy.set(&key, record.borrow().get_value());
+        }
+        copy
+    }
+}
+
+/// Dictionaries show their keys and values rather than the cells that hold them
+impl<K: Hash + Eq + Ord + Clone + fmt::Debug> fmt::Debug for Dictionary<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.entries().into_iter().map(|(key, record)| (key, record.borrow().get_value()))).finish()
+    }
 }
 
-impl Dictionary {
+/// A list of data. Like a dictionary, a list is a handle and cloning it gives another
+/// handle to the same items
+#[derive(Clone)]
+pub(crate) struct List {
+    data: Rc<RefCell<Vec<Rc<RefCell<RecordData>>>>>
+}
+
+impl List {
     pub(crate) fn new() -> Self {
         Self {
-            data: HashMap::new()
+            data: Rc::new(RefCell::new(Vec::new()))
         }
     }
 
+    /// Number of items in the list
+    pub(crate) fn len(&self) -> usize {
+        self.data.borrow().len()
+    }
+
     /// Get a record
-    pub(crate) fn get(&self, key: &String) -> Option<Rc<RefCell<RecordData>>> {
-        match self.data.get(key) {
-            Some(record) => {
-                return Some(record.clone());
+    pub(crate) fn get(&self, index: &Integer) -> Option<Rc<RefCell<RecordData>>> {
+        match index.to_usize() {
+            Some(i) => { self.data.borrow().get(i).cloned() }
+            None    => { None }
+        }
+    }
+
+    /// Iterate over the records. The list isn't held on to, so it can be changed while it is walked
+    pub(crate) fn iter(&self) -> std::vec::IntoIter<Rc<RefCell<RecordData>>> {
+        self.data.borrow().clone().into_iter()
+    }
+
+    /// Add a record to the end of the list
+    pub(crate) fn push(&mut self, value: RecordData) {
+
+        self.data.borrow_mut().push(Rc::new(RefCell::new(value)));
+    }
+
+    /// Remove the record at the end of the list
+    pub(crate) fn pop(&mut self) -> Option<RecordData> {
+
+        let popped = self.data.borrow_mut().pop();
+        popped.map(|record| record.borrow().get_value())
+    }
+
+    /// Insert a record before the given index. An index equal to the length appends the record
+    pub(crate) fn insert(&mut self, index: &Integer, value: RecordData) -> bool {
+
+        let mut data = self.data.borrow_mut();
+
+        match index.to_usize() {
+            Some(i) if i <= data.len() => {
+                data.insert(i, Rc::new(RefCell::new(value)));
+                true
             }
-            None => {
-                return None;
+            _ => { false }
+        }
+    }
+
+    /// Attempt to remove the record at the given index
+    pub(crate) fn remove(&mut self, index: &Integer) -> Option<RecordData> {
+
+        let mut data = self.data.borrow_mut();
+
+        match index.to_usize() {
+            Some(i) if i < data.len() => {
+                let removed = data.remove(i);
+                let value = removed.borrow().get_value();
+                Some(value)
             }
+            _ => { None }
         }
     }
 
-    /// Set a record to record data
-    pub(crate) fn set(&mut self, key: &String, value: RecordData) {
+    /// Put the records in order with the given comparison
+    pub(crate) fn sort_by<F: FnMut(&RecordData, &RecordData) -> Ordering>(&mut self, mut compare: F) {
 
-        self.data.insert(key.clone(), Rc::new(RefCell::new(value)));
+        self.data.borrow_mut().sort_by(|lhs, rhs| compare(&lhs.borrow(), &rhs.borrow()));
     }
 
-    /// Attempt to remove a key
-    pub(crate) fn remove(&mut self, key: &String) -> bool {
+    /// Check if two handles are to the same list
+    pub(crate) fn shares(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.data, &other.data)
+    }
 
-        if self.data.contains_key(key) {
-            self.data.remove_entry(key);
-            return true;
+    /// A new list with the same items. Dictionaries and lists held by this one are shared
+    /// with the copy rather than copied themselves
+    pub(crate) fn copy(&self) -> Self {
+
+        let mut copy = Self::new();
+
+        for record in self.iter() {
+            copy.push(record.borrow().get_value());
         }
-        false
+        copy
+    }
+}
+
+/// Lists show their items rather than the cells that hold them
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter().map(|record| record.borrow().get_value())).finish()
     }
 }
 
+/// A user defined function. The body is shared between every copy of the function, and
+/// functions created by the virtual machine also carry their compiled code
+#[derive(Clone)]
+pub(crate) struct Function {
+    pub(crate) name: String,
+    pub(crate) params: Vec<String>,
+    pub(crate) body: Rc<Vec<Box<Statement>>>,
+    pub(crate) compiled: Option<Rc<Chunk>>
+}
+
+/// Functions only show their signature, the body would be an AST dump
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.params.join(", "))
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use rug::{Integer, Float, Rational, Complex};
+
+extern crate micron_ast;
+use micron_ast::FLOAT_PRECISION;
+
+use crate::types::{ Dictionary, List, RecordData };
+use crate::error::ExecutionError;
+
+/// A value handed between Micron and the application it is embedded in
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Integer(Integer),
+    Float(Float),
+    Rational(Rational),
+    Complex(Complex),
+    Bool(bool),
+    Nil,
+    String(String),
+    Dict(BTreeMap<Key, Value>),
+    List(Vec<Value>),
+
+    /// Signature of a function defined by a script. Functions can only be 
+    /// read by the host, never handed to the engine
+    Function(String)
+}
+
+impl Value {
+
+    /// Build a value from the record data held within the engine
+    pub(crate) fn from_record(record: &RecordData) -> Value {
+        match record {
+            RecordData::Integer(v) => Value::Integer(v.clone()),
+            RecordData::Float(v)   => Value::Float(v.clone()),
+            RecordData::Rational(v) => Value::Rational(v.clone()),
+            RecordData::Complex(v) => Value::Complex(v.clone()),
+            RecordData::Bool(v)    => Value::Bool(*v),
+            RecordData::Nil        => Value::Nil,
+            RecordData::String(v)  => Value::String(v.clone()),
+            RecordData::Dict(v)    => {
+                Value::Dict(v.entries().into_iter().map(|(key, record)| {
+                    (key, Value::from_record(&record.borrow()))
+                }).collect())
+            }
+            RecordData::List(v)    => {
+                Value::List(v.iter().map(|record| Value::from_record(&record.borrow())).collect())
+            }
+            RecordData::Function(v) => Value::Function(format!("{:?}", v))
+        }
+    }
+
+    /// Build record data for the engine from the value. Functions can not be 
+    /// converted as there is no body to go along with them
+    pub(crate) fn into_record(self) -> Option<RecordData> {
+        match self {
+            Value::Integer(v) => Some(RecordData::Integer(v)),
+            Value::Float(v)   => Some(RecordData::Float(v)),
+            Value::Rational(v) => Some(RecordData::Rational(v)),
+            Value::Complex(v) => Some(RecordData::Complex(v)),
+            Value::Bool(v)    => Some(RecordData::Bool(v)),
+            Value::Nil        => Some(RecordData::Nil),
+            Value::String(v)  => Some(RecordData::String(v)),
+            Value::Dict(v)    => {
+                let mut dictionary = Dictionary::new();
+                for (key, value) in v {
+                    dictionary.set(&key, value.into_record()?);
+                }
+                Some(RecordData::Dict(dictionary))
+            }
+            Value::List(v)    => {
+                let mut list = List::new();
+                for value in v {
+                    list.push(value.into_record()?);
+                }
+                Some(RecordData::List(list))
+            }
+            Value::Function(_) => None
+        }
+    }
+}
+
+/// A key of a dictionary. Only strings, integers and bools can be keys, they are the items that 
+/// are always equal to themselves. Keys are ordered bools first, then integers, then strings
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Key {
+    Bool(bool),
+    Integer(Integer),
+    String(String)
+}
+
+impl Key {
+
+    /// Build a key out of record data, if it can be used as one
+    pub(crate) fn from_record(record: &RecordData) -> Result<Key, ExecutionError> {
+        match record {
+            RecordData::Bool(v)    => Ok(Key::Bool(*v)),
+            RecordData::Integer(v) => Ok(Key::Integer(v.clone())),
+            RecordData::String(v)  => Ok(Key::String(v.clone())),
+            _ => Err(ExecutionError::InvalidOperation("Dictionary keys must be strings, integers or bools"))
+        }
+    }
+
+    /// The key as record data, for when keys are handed to a script
+    pub(crate) fn to_record(&self) -> RecordData {
+        match self {
+            Key::Bool(v)    => RecordData::Bool(*v),
+            Key::Integer(v) => RecordData::Integer(v.clone()),
+            Key::String(v)  => RecordData::String(v.clone())
+        }
+    }
+}
+
+/// Keys show only what they hold, so a dictionary reads as {"a": ..., 1: ...}
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Bool(v)    => write!(f, "{:?}", v),
+            Key::Integer(v) => write!(f, "{:?}", v),
+            Key::String(v)  => write!(f, "{:?}", v)
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Key::Bool(v)    => write!(f, "{}", v),
+            Key::Integer(v) => write!(f, "{}", v),
+            Key::String(v)  => write!(f, "{}", v)
+        }
+    }
+}
+
+impl From<&str> for Key {
+    fn from(v: &str) -> Self {
+        Key::String(v.to_string())
+    }
+}
+
+impl From<String> for Key {
+    fn from(v: String) -> Self {
+        Key::String(v)
+    }
+}
+
+impl From<i64> for Key {
+    fn from(v: i64) -> Self {
+        Key::Integer(Integer::from(v))
+    }
+}
+
+impl From<bool> for Key {
+    fn from(v: bool) -> Self {
+        Key::Bool(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::Integer(Integer::from(v))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::Float(Float::with_val(FLOAT_PRECISION, v))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::Bool(v)
+    }
+}
+
+impl From<Integer> for Value {
+    fn from(v: Integer) -> Self {
+        Value::Integer(v)
+    }
+}
+
+impl From<Float> for Value {
+    fn from(v: Float) -> Self {
+        Value::Float(v)
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(v: Rational) -> Self {
+        Value::Rational(v)
+    }
+}
+
+impl From<Complex> for Value {
+    fn from(v: Complex) -> Self {
+        Value::Complex(v)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Value::List(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::String(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::String(v)
+    }
+}
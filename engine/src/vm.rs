@@ -0,0 +1,877 @@
+/*
+
+    A stack based virtual machine that runs the bytecode made by the compiler. It behaves the same
+    as the engine but doesn't walk (and clone) the AST, and values that are only used in passing
+    are kept on the stack as they are rather than being put into records.
+*/
+
+use std::{ cell::RefCell, rc::Rc };
+use std::collections::HashMap;
+
+use micron_ast::{ Statement, Span, FLOAT_PRECISION };
+
+use crate::types::{ Dictionary, List, RecordData };
+use crate::error::{ ExecutionError, EvalError };
+use crate::value::{ Value, Key };
+use crate::host::{ self, HostFunction, NativeFunction };
+use crate::bytecode::{ Chunk, Instruction };
+use crate::compiler::Compiler;
+use crate::ops::{ self, Iteration };
+use crate::math;
+
+/// Something on the stack of the virtual machine
+#[derive(Debug, Clone)]
+enum Operand {
+
+    // A value that isn't stored anywhere
+    Value(RecordData),
+
+    // A variable, or something inside of one
+    Record(Rc<RefCell<RecordData>>),
+
+    // The result of something that didn't result in anything
+    Nothing,
+
+    // What a for loop is walking over
+    Iteration(Box<Iteration>)
+}
+
+impl Operand {
+
+    /// Take the value of the operand to assign it. Something that resulted in nothing, like
+    /// a call to a function without a return, assigns nil
+    fn into_assigned(self) -> Result<RecordData, ExecutionError> {
+        match self {
+            Operand::Nothing => { Ok(RecordData::Nil) }
+            operand => { operand.into_value() }
+        }
+    }
+
+    /// Take the value of the operand
+    fn into_value(self) -> Result<RecordData, ExecutionError> {
+        match self {
+            Operand::Value(value)   => { Ok(value) }
+            Operand::Record(record) => { Ok(record.borrow().get_value()) }
+            Operand::Nothing | Operand::Iteration(_) => { Err(ExecutionError::StackError) }
+        }
+    }
+
+    /// Take the operand as a record that can be changed
+    fn into_record(self) -> Result<Rc<RefCell<RecordData>>, ExecutionError> {
+        match self {
+            Operand::Value(value)   => { Ok(Rc::new(RefCell::new(value))) }
+            Operand::Record(record) => { Ok(record) }
+            Operand::Nothing | Operand::Iteration(_) => { Err(ExecutionError::StackError) }
+        }
+    }
+}
+
+/// A variable along with the depth of the scope it was created in
+#[derive(Debug, Clone)]
+struct Slot {
+    record: Rc<RefCell<RecordData>>,
+    depth: usize
+}
+
+/// The variables of the top level or of a function call
+#[derive(Debug, Clone)]
+struct Scopes {
+    slots: Vec<Option<Slot>>,
+
+    /// Slots that have a variable, in the order they were created
+    created: Vec<usize>,
+
+    /// Number of scopes, and the lowest that it can go
+    depth: usize,
+    floor: usize,
+
+    /// Scope depths and stack sizes to return to
+    marks: Vec<(usize, usize)>,
+
+    /// Value handed out of a scope by a yield
+    yielded: Option<RecordData>
+}
+
+impl Scopes {
+
+    fn new(size: usize, floor: usize) -> Self {
+        Self {
+            slots: vec![None; size],
+            created: Vec::new(),
+            depth: 1,
+            floor,
+            marks: Vec::new(),
+            yielded: None
+        }
+    }
+
+    /// Get the variable in a slot
+    fn get(&self, slot: usize) -> Option<&Slot> {
+        self.slots[slot].as_ref()
+    }
+
+    /// Set a variable, updating it if it already exists and creating it in the current scope otherwise
+    fn set(&mut self, slot: usize, value: RecordData) {
+
+        match &self.slots[slot] {
+            Some(existing) => {
+                existing.record.borrow_mut().update_value(value);
+            }
+            None => {
+                self.slots[slot] = Some(Slot { record: Rc::new(RefCell::new(value)), depth: self.depth });
+                self.created.push(slot);
+            }
+        }
+    }
+
+    /// Remove a variable from the current scope
+    fn remove(&mut self, slot: usize) -> bool {
+
+        match &self.slots[slot] {
+            Some(existing) if existing.depth == self.depth => {
+                self.slots[slot] = None;
+                self.created.retain(|created| *created != slot);
+                true
+            }
+            _ => { false }
+        }
+    }
+
+    /// Throw away every scope deeper than the given depth
+    fn return_to(&mut self, depth: usize) {
+
+        while let Some(slot) = self.created.last() {
+
+            match &self.slots[*slot] {
+                Some(existing) if existing.depth > depth => {
+                    self.slots[*slot] = None;
+                    self.created.pop();
+                }
+                _ => { break; }
+            }
+        }
+        self.depth = depth;
+    }
+
+    /// Remove the current scope
+    fn pop_scope(&mut self) {
+
+        let depth = self.depth;
+        self.return_to(depth - 1);
+
+        if self.depth < self.floor {
+            self.depth = self.floor;
+        }
+    }
+}
+
+/// A function being run
+struct Frame {
+    chunk: Rc<Chunk>,
+    ip: usize,
+
+    /// Size of the stack when the function was called
+    stack_base: usize,
+
+    /// None for the top level, which uses the global scopes
+    scopes: Option<Scopes>
+}
+
+/// The Micron virtual machine
+pub struct Vm {
+
+    /// Global variables and the slots of their names
+    symbols: HashMap<String, usize>,
+    globals: Scopes,
+
+    stack: Vec<Operand>,
+    frames: Vec<Frame>,
+
+    /// Print the result of bare expressions as they are executed
+    echo: bool,
+    last_value: Option<RecordData>,
+
+    /// Precision, in bits, that floats are read in and promoted at
+    precision: u32,
+
+    /// Create missing dictionaries along the way when assigning to a nested variable
+    auto_vivify: bool,
+
+    /// Location of the instruction that caused the last error
+    error_location: Option<Span>,
+
+    /// Functions registered by the host application
+    host_functions: HashMap<String, NativeFunction>
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+
+    /// Create a new virtual machine
+    pub fn new() -> Self {
+        Self {
+            symbols: HashMap::new(),
+            globals: Scopes::new(0, 1),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            echo: false,
+            last_value: None,
+            precision: FLOAT_PRECISION,
+            auto_vivify: false,
+            error_location: None,
+            host_functions: HashMap::new()
+        }
+    }
+
+    /// Register a native function that scripts can call with '#name(...)'. The arguments given
+    /// by the script are converted to the types the function takes, and anything the function
+    /// returns is handed back to the script. Names that belong to a built in function are refused
+    ///
+    /// ```
+    /// # use micron_engine::{ Vm, Value };
+    /// let mut vm = Vm::new();
+    /// vm.register_fn("add", |a: i64, b: i64| a + b).unwrap();
+    /// assert_eq!(vm.eval("#add(2, 3) * 2").unwrap(), Some(Value::from(10)));
+    /// ```
+    pub fn register_fn<Args, F: HostFunction<Args>>(&mut self, name: &str, function: F) -> Result<(), ExecutionError> {
+
+        if host::is_built_in(name) {
+            return Err(ExecutionError::ReservedFunctionName(name.to_string()));
+        }
+
+        self.host_functions.insert(name.to_string(), function.into_native());
+        Ok(())
+    }
+
+    /// Set if the result of bare expressions should be printed to stdout as they are executed.
+    /// This is off unless the host turns it on
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Set the precision, in bits, that floats are read in and promoted at from here on
+    pub fn set_precision(&mut self, bits: u32) -> Result<(), ExecutionError> {
+        self.precision = ops::precision_bits(&RecordData::Integer(bits.into()))?;
+        Ok(())
+    }
+
+    /// Precision, in bits, that floats are read in and promoted at
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// Set if assigning to a nested variable creates the dictionaries that are missing along the
+    /// way, so 'a['x']['y'] = 1' works even when 'a' has no key 'x' yet
+    pub fn set_auto_vivify(&mut self, enabled: bool) {
+        self.auto_vivify = enabled;
+    }
+
+    /// Check if assigning to a nested variable creates the dictionaries that are missing along the way
+    pub fn auto_vivify(&self) -> bool {
+        self.auto_vivify
+    }
+
+    /// Parse and execute source code. If the final statement is a bare expression
+    /// its value is handed back instead of being echoed
+    pub fn eval(&mut self, source: &str) -> Result<Option<Value>, EvalError> {
+
+        let source = micron_parser::strip_comments(source);
+
+        let mut statements = match micron_parser::micron::ProgramParser::new().parse(&source) {
+            Ok(statements) => { statements }
+            Err(e) => { return Err(EvalError::ParseError(e.to_string())); }
+        };
+
+        let last_statement = match statements.pop() {
+            Some(statement) => { statement }
+            None => { return Ok(None); }
+        };
+
+        if let Some(e) = self.execute(&statements) {
+            return Err(EvalError::ExecutionError(e));
+        }
+
+        let is_bare_expression = matches!(last_statement.unlocated(), Statement::BareExpression(_));
+
+        // The value of a final bare expression is handed back rather than echoed
+        let echo = self.echo;
+
+        if is_bare_expression {
+            self.echo = false;
+            self.last_value = None;
+        }
+
+        let result = self.execute(&[last_statement]);
+
+        self.echo = echo;
+
+        if let Some(e) = result {
+            return Err(EvalError::ExecutionError(e));
+        }
+
+        match (is_bare_expression, self.last_value.take()) {
+            (true, Some(value)) => { Ok(Some(Value::from_record(&value))) }
+            _ => { Ok(None) }
+        }
+    }
+
+    /// Get a copy of a variable from the global scope
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+
+        let slot = self.symbols.get(name)?;
+
+        match self.globals.get(*slot) {
+            Some(existing) if existing.depth == 1 => { Some(Value::from_record(&existing.record.borrow())) }
+            _ => { None }
+        }
+    }
+
+    /// Set a variable in the global scope, creating it if it doesn't exist
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<(), ExecutionError> {
+
+        let record = match value.into_record() {
+            Some(record) => { record }
+            None => { return Err(ExecutionError::InvalidOperation("Functions can not be handed to the engine")); }
+        };
+
+        let next = self.symbols.len();
+        let slot = *self.symbols.entry(name.to_string()).or_insert(next);
+        self.grow_globals();
+
+        // The global is replaced outright, the same way the engine does it
+        if self.globals.get(slot).is_some() {
+            self.globals.slots[slot] = None;
+            self.globals.created.retain(|created| *created != slot);
+        }
+
+        let depth = self.globals.depth;
+        self.globals.depth = 1;
+        self.globals.set(slot, record);
+        self.globals.depth = depth;
+        Ok(())
+    }
+
+    /// Compile and execute an AST statement
+    pub fn execute_statement(&mut self, statement: Statement) -> Option<ExecutionError> {
+        self.execute(&[Box::new(statement)])
+    }
+
+    /// Compile and execute a list of statements
+    pub fn execute(&mut self, statements: &[Box<Statement>]) -> Option<ExecutionError> {
+
+        self.error_location = None;
+
+        let chunk = Compiler::compile(&mut self.symbols, statements);
+        self.grow_globals();
+
+        let depth = self.globals.depth;
+        let marks = self.globals.marks.len();
+
+        self.frames.push(Frame {
+            chunk: Rc::new(chunk),
+            ip: 0,
+            stack_base: self.stack.len(),
+            scopes: None
+        });
+
+        let result = self.run();
+
+        // Whatever happened, leave things the way they were before the statements ran
+        if result.is_err() {
+            if let Some(frame) = self.frames.last() {
+                self.error_location = frame.chunk.spans.get(frame.ip.saturating_sub(1)).copied().flatten();
+            }
+
+            if self.globals.depth > depth {
+                self.globals.return_to(depth);
+            }
+            self.globals.marks.truncate(marks);
+        }
+
+        self.frames.clear();
+        self.stack.clear();
+
+        result.err()
+    }
+
+    /// Location in the source of the instruction that caused the most recent error
+    pub fn error_location(&self) -> Option<Span> {
+        self.error_location
+    }
+
+    /// Make room for any globals the compiler has added
+    fn grow_globals(&mut self) {
+        if self.globals.slots.len() < self.symbols.len() {
+            self.globals.slots.resize(self.symbols.len(), None);
+        }
+    }
+
+    /// Scopes of the function currently running
+    fn scopes(&mut self) -> &mut Scopes {
+        match self.frames.last_mut().and_then(|frame| frame.scopes.as_mut()) {
+            Some(scopes) => { scopes }
+            None => { &mut self.globals }
+        }
+    }
+
+    /// Find the variable in a slot. Functions fall back on the global scope
+    fn load(&self, slot: usize) -> Result<Rc<RefCell<RecordData>>, ExecutionError> {
+
+        let frame = match self.frames.last() {
+            Some(frame) => { frame }
+            None => { return Err(ExecutionError::StackError); }
+        };
+
+        match &frame.scopes {
+            None => {
+                match self.globals.get(slot) {
+                    Some(existing) => { Ok(existing.record.clone()) }
+                    None => { Err(ExecutionError::UnknownVariable) }
+                }
+            }
+            Some(scopes) => {
+                if let Some(existing) = scopes.get(slot) {
+                    return Ok(existing.record.clone());
+                }
+
+                match self.globals.get(frame.chunk.globals[slot]) {
+                    Some(existing) if existing.depth == 1 => { Ok(existing.record.clone()) }
+                    _ => { Err(ExecutionError::UnknownVariable) }
+                }
+            }
+        }
+    }
+
+    /// Follow the given keys into a nested variable
+    fn load_nested(&self, slot: usize, keys: Vec<RecordData>) -> Result<Rc<RefCell<RecordData>>, ExecutionError> {
+
+        let mut record = self.load(slot)?;
+
+        for key in keys.iter() {
+            record = ops::element(&record, key)?;
+        }
+        Ok(record)
+    }
+
+    fn pop(&mut self) -> Result<Operand, ExecutionError> {
+        self.stack.pop().ok_or(ExecutionError::StackError)
+    }
+
+    /// Take the top items off of the stack as values
+    fn pop_values(&mut self, count: usize) -> Result<Vec<RecordData>, ExecutionError> {
+
+        if self.stack.len() < count {
+            return Err(ExecutionError::StackError);
+        }
+
+        let at = self.stack.len() - count;
+        self.stack.split_off(at).into_iter().map(Operand::into_value).collect()
+    }
+
+    /// Take the top of the stack as a condition
+    fn pop_truthy(&mut self) -> Result<bool, ExecutionError> {
+        match self.pop()? {
+            Operand::Value(value)   => { Ok(value.is_truthy()) }
+            Operand::Record(record) => { Ok(record.borrow().is_truthy()) }
+            Operand::Nothing | Operand::Iteration(_) => { Err(ExecutionError::StackError) }
+        }
+    }
+
+    /// Leave the current function, handing back its result
+    fn leave_function(&mut self, result: Operand) {
+
+        if let Some(frame) = self.frames.pop() {
+            self.stack.truncate(frame.stack_base);
+        }
+        self.stack.push(result);
+    }
+
+    /// Run until the top level chunk is finished
+    fn run(&mut self) -> Result<(), ExecutionError> {
+
+        loop {
+
+            let frame = match self.frames.last_mut() {
+                Some(frame) => { frame }
+                None => { return Ok(()); }
+            };
+
+            let instruction = match frame.chunk.code.get(frame.ip) {
+                Some(instruction) => { *instruction }
+                None => { return Ok(()); }
+            };
+
+            frame.ip += 1;
+
+            match instruction {
+
+                Instruction::Constant(index) => {
+                    let value = frame.chunk.constants[index].clone();
+                    self.stack.push(Operand::Value(value));
+                }
+
+                Instruction::Format(spec) => {
+                    let spec = frame.chunk.formats[spec].clone();
+                    let value = self.pop()?.into_value()?;
+                    let formatted = ops::format(&value, &spec)?;
+                    self.stack.push(Operand::Value(RecordData::String(formatted)));
+                }
+
+                Instruction::Concat(count) => {
+
+                    let mut result = String::new();
+
+                    for part in self.pop_values(count)? {
+                        if let RecordData::String(part) = part {
+                            result.push_str(&part);
+                        }
+                    }
+                    self.stack.push(Operand::Value(RecordData::String(result)));
+                }
+
+                Instruction::Real(digits) => {
+                    let value = ops::parse_float(&frame.chunk.digits[digits], self.precision)?;
+                    self.stack.push(Operand::Value(RecordData::Float(value)));
+                }
+
+                Instruction::Imaginary(digits) => {
+                    let value = ops::parse_imaginary(&frame.chunk.digits[digits], self.precision)?;
+                    self.stack.push(Operand::Value(RecordData::Complex(value)));
+                }
+
+                Instruction::Nothing => {
+                    self.stack.push(Operand::Nothing);
+                }
+
+                Instruction::Load(slot) => {
+                    let record = self.load(slot)?;
+                    self.stack.push(Operand::Record(record));
+                }
+
+                Instruction::LoadNested(slot, count) => {
+                    let keys = self.pop_values(count)?;
+                    let record = self.load_nested(slot, keys)?;
+                    self.stack.push(Operand::Record(record));
+                }
+
+                Instruction::Store(slot) => {
+                    let value = self.pop()?.into_assigned()?;
+                    self.scopes().set(slot, value);
+                }
+
+                Instruction::StoreNested(slot, count) => {
+                    let keys = self.pop_values(count)?;
+                    let value = self.pop()?.into_assigned()?;
+
+                    // The variable has to exist, but the key being assigned doesn't
+                    ops::assign(self.load(slot)?, &keys, value, self.auto_vivify)?;
+                }
+
+                Instruction::Drop(slot) => {
+                    if !self.scopes().remove(slot) {
+                        return Err(ExecutionError::UnknownVariable);
+                    }
+                    self.stack.push(Operand::Value(RecordData::Bool(true)));
+                }
+
+                Instruction::MakeDict(count) => {
+
+                    // Keys and values are on the stack in pairs, each key below its value
+                    let items = self.pop_values(count * 2)?;
+
+                    let mut new_dict = Dictionary::new();
+
+                    for pair in items.chunks(2) {
+                        new_dict.set(&Key::from_record(&pair[0])?, pair[1].clone());
+                    }
+                    self.stack.push(Operand::Value(RecordData::Dict(new_dict)));
+                }
+
+                Instruction::MakeList(count) => {
+
+                    let mut new_list = List::new();
+
+                    for value in self.pop_values(count)? {
+                        new_list.push(value);
+                    }
+                    self.stack.push(Operand::Value(RecordData::List(new_list)));
+                }
+
+                Instruction::MakeFunction(index) => {
+                    let function = frame.chunk.functions[index].clone();
+                    self.stack.push(Operand::Value(RecordData::Function(function)));
+                }
+
+                Instruction::Binary(op) => {
+                    let rhs = self.pop()?.into_value()?;
+                    let lhs = self.pop()?.into_value()?;
+                    self.stack.push(Operand::Value(ops::binary(lhs, rhs, op, self.precision)?));
+                }
+
+                Instruction::Unary(op) => {
+                    let item = self.pop()?.into_value()?;
+                    self.stack.push(Operand::Value(ops::unary(item, op)?));
+                }
+
+                Instruction::Method(name, count) => {
+
+                    let chunk = frame.chunk.clone();
+                    let arguments = self.pop_values(count)?;
+                    let item = self.pop()?.into_record()?;
+
+                    match ops::call_method(&item, &chunk.names[name], arguments, self.precision)? {
+                        Some(value) => { self.stack.push(Operand::Value(value)); }
+                        None => { self.stack.push(Operand::Nothing); }
+                    }
+                }
+
+                Instruction::Call(count) => {
+
+                    let arguments = self.pop_values(count)?;
+
+                    let function = match self.pop()?.into_value()? {
+                        RecordData::Function(f) => { f }
+                        _ => { return Err(ExecutionError::InvalidOperation("Attempted to call a non-function type")); }
+                    };
+
+                    if function.params.len() != arguments.len() {
+                        return Err(ExecutionError::InvalidParameters);
+                    }
+
+                    let chunk = match function.compiled {
+                        Some(chunk) => { chunk }
+                        None => { return Err(ExecutionError::InvalidOperation("Attempted to call a function that was not compiled")); }
+                    };
+
+                    // The first frame is the program itself, every other frame is a call in progress
+                    if self.frames.len() > ops::MAX_CALL_DEPTH {
+                        return Err(ExecutionError::CallDepthExceeded(ops::MAX_CALL_DEPTH));
+                    }
+
+                    // Parameters take the first slots of the function
+                    let mut scopes = Scopes::new(chunk.globals.len(), 0);
+                    scopes.marks.push((0, self.stack.len()));
+
+                    for (slot, value) in arguments.into_iter().enumerate() {
+                        scopes.set(slot, value);
+                    }
+
+                    self.frames.push(Frame {
+                        chunk,
+                        ip: 0,
+                        stack_base: self.stack.len(),
+                        scopes: Some(scopes)
+                    });
+                }
+
+                Instruction::BuiltIn(name, count) => {
+
+                    let name = frame.chunk.names[name].clone();
+
+                    if name == "precision" {
+
+                        let arguments = self.pop_values(count)?;
+
+                        match ops::precision(&mut self.precision, arguments)? {
+                            Some(value) => { self.stack.push(Operand::Value(value)); }
+                            None => { self.stack.push(Operand::Nothing); }
+                        }
+                        continue;
+                    }
+
+                    if name == "auto_vivify" {
+
+                        let arguments = self.pop_values(count)?;
+
+                        match ops::auto_vivify(&mut self.auto_vivify, arguments)? {
+                            Some(value) => { self.stack.push(Operand::Value(value)); }
+                            None => { self.stack.push(Operand::Nothing); }
+                        }
+                        continue;
+                    }
+
+                    if math::is_function(&name) {
+
+                        let arguments = self.pop_values(count)?;
+                        self.stack.push(Operand::Value(math::call(&name, arguments, self.precision)?));
+                        continue;
+                    }
+
+                    let function = match self.host_functions.get(&name) {
+                        Some(function) => { function.clone() }
+                        None => { return Err(ExecutionError::UnknownBuiltInFunction(name)); }
+                    };
+
+                    let arguments = self.pop_values(count)?;
+                    let arguments = arguments.iter().map(Value::from_record).collect();
+
+                    match function.call(arguments, self.precision)? {
+                        Some(value) => {
+                            match value.into_record() {
+                                Some(record) => { self.stack.push(Operand::Value(record)); }
+                                None => { return Err(ExecutionError::InvalidOperation("Host functions can not return functions")); }
+                            }
+                        }
+                        None => { self.stack.push(Operand::Nothing); }
+                    }
+                }
+
+                Instruction::Echo => {
+
+                    let value = match self.pop()? {
+                        Operand::Value(value)   => { value }
+                        Operand::Record(record) => { record.borrow().get_value() }
+                        Operand::Nothing        => { continue; }
+                        Operand::Iteration(_)   => { return Err(ExecutionError::StackError); }
+                    };
+
+                    if self.echo {
+                        println!("{:?}", Value::from_record(&value));
+                    }
+                    self.last_value = Some(value);
+                }
+
+                Instruction::Yield => {
+                    let value = self.pop()?.into_value()?;
+                    let scopes = self.scopes();
+                    scopes.pop_scope();
+                    scopes.yielded = Some(value);
+                }
+
+                Instruction::Yielded => {
+                    let value = match self.scopes().yielded.take() {
+                        Some(value) => { Operand::Value(value) }
+                        None => { Operand::Nothing }
+                    };
+                    self.stack.push(value);
+                }
+
+                Instruction::Return => {
+                    let value = self.pop()?.into_value()?;
+                    self.leave_function(Operand::Value(value));
+                }
+
+                Instruction::ReturnNothing => {
+                    self.leave_function(Operand::Nothing);
+                }
+
+                Instruction::ReturnYielded => {
+                    let value = match self.scopes().yielded.take() {
+                        Some(value) => { Operand::Value(value) }
+                        None => { Operand::Nothing }
+                    };
+                    self.leave_function(value);
+                }
+
+                Instruction::Jump(target) => {
+                    frame.ip = target;
+                }
+
+                Instruction::JumpIfFalse(target) => {
+
+                    if !self.pop_truthy()? {
+                        if let Some(frame) = self.frames.last_mut() {
+                            frame.ip = target;
+                        }
+                    }
+                }
+
+                Instruction::JumpIfTrue(target) => {
+
+                    if self.pop_truthy()? {
+                        if let Some(frame) = self.frames.last_mut() {
+                            frame.ip = target;
+                        }
+                    }
+                }
+
+                Instruction::Iterate(pairs) => {
+                    let item = self.pop()?.into_value()?;
+                    self.stack.push(Operand::Iteration(Box::new(ops::iterate(item, pairs)?)));
+                }
+
+                Instruction::Range(inclusive) => {
+                    let end = self.pop()?.into_value()?;
+                    let start = self.pop()?.into_value()?;
+                    self.stack.push(Operand::Iteration(Box::new(ops::range(start, end, inclusive)?)));
+                }
+
+                Instruction::Next(target) => {
+
+                    let item = match self.stack.last_mut() {
+                        Some(Operand::Iteration(iteration)) => { iteration.next() }
+                        _ => { return Err(ExecutionError::StackError); }
+                    };
+
+                    match item {
+                        Some(item) => { self.stack.push(Operand::Value(item)); }
+                        None => { frame.ip = target; }
+                    }
+                }
+
+                Instruction::Unpack => {
+                    let (key, value) = ops::unpack(self.pop()?.into_value()?)?;
+                    self.stack.push(Operand::Value(key));
+                    self.stack.push(Operand::Value(value));
+                }
+
+                Instruction::Pop => {
+                    self.pop()?;
+                }
+
+                Instruction::EnterScope => {
+                    self.scopes().depth += 1;
+                }
+
+                Instruction::Mark => {
+                    let stack_size = self.stack.len();
+                    let scopes = self.scopes();
+                    let depth = scopes.depth;
+                    scopes.marks.push((depth, stack_size));
+                }
+
+                Instruction::Restore => {
+
+                    let (depth, stack_size) = match self.scopes().marks.last() {
+                        Some(mark) => { *mark }
+                        None => { return Err(ExecutionError::StackError); }
+                    };
+
+                    self.scopes().return_to(depth);
+                    self.stack.truncate(stack_size);
+                }
+
+                Instruction::Unmark(count) => {
+                    let scopes = self.scopes();
+                    let remaining = scopes.marks.len().saturating_sub(count);
+                    scopes.marks.truncate(remaining);
+                }
+
+                Instruction::CheckScope(target) => {
+
+                    let scopes = self.scopes();
+
+                    let lost = match scopes.marks.last() {
+                        Some((depth, _)) => { scopes.depth <= *depth }
+                        None => { false }
+                    };
+
+                    if lost {
+                        if let Some(frame) = self.frames.last_mut() {
+                            frame.ip = target;
+                        }
+                    }
+                }
+
+                Instruction::Fail(error) => {
+                    return Err(error());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+/*
+
+    Pins down how deep function calls can go. Every case is run through both the tree walking
+    engine and the virtual machine, on a thread with the stack a host is expected to give them
+*/
+
+use micron_engine::{ Engine, Vm, Value, EvalError, ExecutionError, MAX_CALL_DEPTH, STACK_SIZE };
+
+/// Counts down to zero, making one call for each step along the way
+const COUNTDOWN: &str = "fn f(n) { if n == 0 { return 0 } return f(n - 1) + 1 }";
+
+/// Run a program in both backends on a thread with enough stack to reach the maximum call depth
+fn run(source: String) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let runner = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+
+        let mut engine = Engine::new();
+        let mut vm = Vm::new();
+
+        (engine.eval(&source), vm.eval(&source))
+    });
+
+    runner.unwrap().join().unwrap()
+}
+
+/// Check that a program was stopped for calling too deep
+fn too_deep(result: &Result<Option<Value>, EvalError>) -> bool {
+    matches!(result, Err(EvalError::ExecutionError(ExecutionError::CallDepthExceeded(_))))
+}
+
+#[test]
+fn calls_can_reach_the_maximum_depth() {
+
+    let steps = MAX_CALL_DEPTH - 1;
+    let (from_engine, from_vm) = run(format!("{}  f({})", COUNTDOWN, steps));
+
+    assert_eq!(from_engine.unwrap(), Some(Value::from(steps as i64)));
+    assert_eq!(from_vm.unwrap(), Some(Value::from(steps as i64)));
+}
+
+#[test]
+fn calls_past_the_maximum_depth_are_refused() {
+
+    let (from_engine, from_vm) = run(format!("{}  f({})", COUNTDOWN, MAX_CALL_DEPTH));
+
+    assert!(too_deep(&from_engine), "engine gave {:?}", from_engine);
+    assert!(too_deep(&from_vm), "vm gave {:?}", from_vm);
+
+    let (from_engine, from_vm) = run("fn f(n) { return f(n + 1) }  f(0)".to_string());
+
+    assert!(too_deep(&from_engine), "engine gave {:?}", from_engine);
+    assert!(too_deep(&from_vm), "vm gave {:?}", from_vm);
+}
+
+#[test]
+fn assigning_nothing_gives_nil() {
+
+    let sources = [
+        "fn f(a) { a }  r = f(1)  r == nil",
+        "b = if 0 { yield 5 }  b == nil",
+        "fn f() { x = 1 }  d = {}  d['k'] = f()  d['k'] == nil",
+        "l = []  r = l.push(1)  r == nil",
+    ];
+
+    for source in sources.iter() {
+
+        let (from_engine, from_vm) = run(source.to_string());
+
+        assert_eq!(from_engine.unwrap(), Some(Value::from(true)), "engine: {}", source);
+        assert_eq!(from_vm.unwrap(), Some(Value::from(true)), "vm: {}", source);
+    }
+}
@@ -0,0 +1,113 @@
+/*
+
+    Pins down how items compare. Numbers of different types are compared by their exact value
+    and sorting places items of different types by their type. Every case is run through both
+    the tree walking engine and the virtual machine
+*/
+
+use micron_engine::{ Engine, Vm, Value };
+use rug::Rational;
+
+/// Comparisons and whether they hold
+const CASES: &[(&str, bool)] = &[
+
+    // Floats are compared by the exact value they hold
+    ("0.5 == 1r / 2",                               true),
+    ("0.1 == 1r / 10",                              false),
+    ("0.1 != 1r / 10",                              true),
+    ("0.1 > 1r / 10",                               true),
+    ("2.0 == 2",                                    true),
+    ("2 ** 70 == (2 ** 70).as_float()",             true),
+    ("2 ** 70 + 1 == (2 ** 70 + 1).as_float()",     false),
+    ("2 ** 70 + 1 > (2 ** 70 + 1).as_float()",      true),
+    ("4r / 2 == 2",                                 true),
+    ("3i == 3",                                     false),
+    ("(2 + 0i) == 2",                               true),
+
+    // Items of different kinds are never equal
+    ("1 == true",                                   false),
+    ("0 == nil",                                    false),
+    ("\"1\" == 1",                                  false),
+    ("[1, 2] == [1, 2.0]",                          true),
+    ("[1, 2] == [1, 2, 3]",                         false),
+];
+
+/// Evaluate a program in both backends
+fn evaluate(source: &str) -> (Option<Value>, Option<Value>) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    let from_engine = engine.eval(source).unwrap_or_else(|e| panic!("engine failed on '{}' : {:?}", source, e));
+    let from_vm = vm.eval(source).unwrap_or_else(|e| panic!("vm failed on '{}' : {:?}", source, e));
+
+    (from_engine, from_vm)
+}
+
+#[test]
+fn numbers_compare_by_exact_value() {
+
+    for (source, expected) in CASES {
+
+        let (from_engine, from_vm) = evaluate(&format!("result = {}\nresult", source));
+
+        assert_eq!(from_engine, Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(from_vm, Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
+
+#[test]
+fn sorting_orders_by_type_then_value() {
+
+    let source = "l = [3, \"b\", [1], 1.5, nil, true, 2r / 3, \"a\", false, 1]\nl.sort()\nl";
+
+    let expected = Value::from(vec![
+        Value::Nil,
+        Value::from(false),
+        Value::from(true),
+        Value::from(Rational::from((2, 3))),
+        Value::from(1),
+        Value::from(1.5),
+        Value::from(3),
+        Value::from("a"),
+        Value::from("b"),
+        Value::from(vec![Value::from(1)]),
+    ]);
+
+    let (from_engine, from_vm) = evaluate(source);
+
+    assert_eq!(from_engine, Some(expected.clone()));
+    assert_eq!(from_vm, Some(expected));
+}
+
+#[test]
+fn sorting_keeps_equal_numbers_in_order() {
+
+    let source = "l = [2, 1r / 2, 2.0, 0.5, 1]\nl.sort()\nl";
+
+    let expected = Value::from(vec![
+        Value::from(Rational::from((1, 2))),
+        Value::from(0.5),
+        Value::from(1),
+        Value::from(2),
+        Value::from(2.0),
+    ]);
+
+    let (from_engine, from_vm) = evaluate(source);
+
+    assert_eq!(format!("{:?}", from_engine), format!("{:?}", Some(expected.clone())));
+    assert_eq!(format!("{:?}", from_vm), format!("{:?}", Some(expected)));
+}
+
+#[test]
+fn ordering_other_types_is_refused() {
+
+    for source in ["result = [1] < [2]", "result = nil < 1", "result = 1i < 2", "result = \"a\" < 1"].iter() {
+
+        let mut engine = Engine::new();
+        let mut vm = Vm::new();
+
+        assert!(engine.eval(source).is_err(), "engine: {}", source);
+        assert!(vm.eval(source).is_err(), "vm: {}", source);
+    }
+}
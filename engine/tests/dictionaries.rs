@@ -0,0 +1,149 @@
+/*
+
+    Pins down dictionary methods, the keys dictionaries take and how assigning to a nested key
+    creates what is missing. Every case is run through both the tree walking engine and the
+    virtual machine
+*/
+
+use std::collections::BTreeMap;
+
+use micron_engine::{ Engine, Vm, Value, Key, EvalError, ExecutionError };
+
+/// Dictionary every case starts out with
+const SETUP: &str = "d = { 'b' : 2, 'a' : 1, 1 : 'one', true : 'yes' }\n";
+
+fn list(items: Vec<Value>) -> Value {
+    Value::from(items)
+}
+
+/// Evaluate a program in both backends
+fn run(source: &str, auto_vivify: bool) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    engine.set_auto_vivify(auto_vivify);
+    vm.set_auto_vivify(auto_vivify);
+
+    (engine.eval(source), vm.eval(source))
+}
+
+/// Evaluate a program in both backends and check what it gives
+fn gives(source: &str, auto_vivify: bool, expected: &Value) {
+
+    let (from_engine, from_vm) = run(source, auto_vivify);
+
+    assert_eq!(from_engine.unwrap_or_else(|e| panic!("engine failed on '{}' : {:?}", source, e)), Some(expected.clone()), "engine: {}", source);
+    assert_eq!(from_vm.unwrap_or_else(|e| panic!("vm failed on '{}' : {:?}", source, e)), Some(expected.clone()), "vm: {}", source);
+}
+
+/// Evaluate a program in both backends and check that it fails the way it should
+fn refused(source: &str, auto_vivify: bool, expected: fn(&ExecutionError) -> bool) {
+
+    let (from_engine, from_vm) = run(source, auto_vivify);
+
+    for result in [from_engine, from_vm].iter() {
+        match result {
+            Err(EvalError::ExecutionError(e)) if expected(e) => { }
+            other => { panic!("'{}' gave {:?}", source, other); }
+        }
+    }
+}
+
+#[test]
+fn dictionary_methods() {
+
+    let cases: Vec<(&str, Value)> = vec![
+        ("d.keys()",                    list(vec![Value::from(true), Value::from(1), Value::from("a"), Value::from("b")])),
+        ("d.values()",                  list(vec![Value::from("yes"), Value::from("one"), Value::from(1), Value::from(2)])),
+        ("i = d.items()\ni[2]",         list(vec![Value::from("a"), Value::from(1)])),
+        ("d.len()",                     Value::from(4)),
+        ("d.contains('a')",             Value::from(true)),
+        ("d.contains('z')",             Value::from(false)),
+        ("d.get('a')",                  Value::from(1)),
+        ("d.get('z')",                  Value::Nil),
+        ("d.get('z', 0)",               Value::from(0)),
+        ("r = d.remove('a')\n[r, d.len(), d.contains('a')]", list(vec![Value::from(1), Value::from(3), Value::from(false)])),
+        ("d.merge({ 'b' : 3, 'c' : 4 })\n[d['b'], d['c'], d.len()]", list(vec![Value::from(3), Value::from(4), Value::from(5)])),
+        ("d.clear()\nd.len()",          Value::from(0)),
+    ];
+
+    for (source, expected) in cases.iter() {
+        gives(&format!("{}{}", SETUP, source), false, expected);
+    }
+}
+
+#[test]
+fn missing_keys_are_named() {
+
+    for source in ["x = d['z']", "x = d.remove('z')", "e = { 'a' : {} }\nx = e['a']['z']", "x = d[2]"].iter() {
+
+        let program = format!("{}{}", SETUP, source);
+        refused(&program, false, |e| matches!(e, ExecutionError::MissingKey(_)));
+    }
+
+    let (from_engine, from_vm) = run(&format!("{}x = d['z']", SETUP), false);
+
+    for result in [from_engine, from_vm].iter() {
+        match result {
+            Err(EvalError::ExecutionError(ExecutionError::MissingKey(key))) => { assert_eq!(key, "z"); }
+            other => { panic!("gave {:?}", other); }
+        }
+    }
+}
+
+#[test]
+fn keys_of_different_types_are_different_keys() {
+
+    let source = "d = { 1 : 'int', '1' : 'string', true : 'bool' }\n[d[1], d['1'], d[true], d.len()]";
+    gives(source, false, &list(vec![Value::from("int"), Value::from("string"), Value::from("bool"), Value::from(3)]));
+
+    // Any expression that gives a key can be used as one
+    gives("a = 1\nd = { (a + 1) : 'two', 'k' + 'ey' : 'key' }\n[d[2], d['key']]", false, &list(vec![Value::from("two"), Value::from("key")]));
+
+    for source in ["d = {}\nd[1.5] = 1", "d = { [1] : 1 }", "d = {}\nx = d[nil]"].iter() {
+        refused(source, false, |e| matches!(e, ExecutionError::InvalidOperation(_)));
+    }
+}
+
+#[test]
+fn dictionaries_are_handed_out_in_key_order() {
+
+    let mut expected = BTreeMap::new();
+    expected.insert(Key::from("a"), Value::from(1));
+    expected.insert(Key::from("b"), Value::from(2));
+    expected.insert(Key::from(1), Value::from("one"));
+    expected.insert(Key::from(true), Value::from("yes"));
+
+    gives(&format!("{}d", SETUP), false, &Value::Dict(expected));
+}
+
+#[test]
+fn assigning_creates_a_missing_last_key() {
+
+    gives("d = {}\nd['a'] = 1\nd['a']", false, &Value::from(1));
+    gives("d = { 'a' : {} }\nd['a']['b'] = 2\nd['a']['b']", false, &Value::from(2));
+    gives("d = { 'a' : [{}] }\nd['a'][0]['b'] = 3\nd['a'][0]['b']", false, &Value::from(3));
+}
+
+#[test]
+fn missing_dictionaries_are_only_created_with_auto_vivify() {
+
+    let source = "d = {}\nd['a']['b']['c'] = 1\nd['a']['b']['c']";
+
+    refused(source, false, |e| matches!(e, ExecutionError::MissingKey(_)));
+    gives(source, true, &Value::from(1));
+
+    // The built in turns it on and off from within a script
+    gives("#auto_vivify(true)\nd = {}\nd['a']['b'] = 1\nd['a']['b']", false, &Value::from(1));
+    gives("#auto_vivify(true)\n#auto_vivify()", false, &Value::from(true));
+    refused("#auto_vivify(false)\nd = {}\nd['a']['b'] = 1", true, |e| matches!(e, ExecutionError::MissingKey(_)));
+}
+
+#[test]
+fn auto_vivify_never_replaces_a_value_or_creates_a_variable() {
+
+    refused("d = { 'a' : 1 }\nd['a']['b'] = 2", true, |e| matches!(e, ExecutionError::InvalidOperation(_)));
+    refused("l = []\nl[0]['b'] = 2", true, |e| matches!(e, ExecutionError::IndexError));
+    refused("x['a'] = 1", true, |e| matches!(e, ExecutionError::UnknownVariable));
+}
@@ -0,0 +1,159 @@
+/*
+
+    Pins down how functions registered by the host are called. Every case is run through both
+    the tree walking engine and the virtual machine
+*/
+
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use micron_engine::{ Engine, Vm, Value, EvalError, ExecutionError };
+
+fn add(a: i64, b: i64) -> i64 {
+    a + b
+}
+
+fn shout(text: String) -> String {
+    text.to_uppercase()
+}
+
+fn halve(n: f64) -> f64 {
+    n / 2.0
+}
+
+fn checked_div(a: i64, b: i64) -> Result<i64, ExecutionError> {
+    if b == 0 {
+        return Err(ExecutionError::DivisionByZero);
+    }
+    Ok(a / b)
+}
+
+/// Build both backends with the same host functions registered
+fn backends() -> (Engine, Vm) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    engine.register_fn("add", add).unwrap();
+    engine.register_fn("shout", shout).unwrap();
+    engine.register_fn("halve", halve).unwrap();
+    engine.register_fn("checked_div", checked_div).unwrap();
+
+    vm.register_fn("add", add).unwrap();
+    vm.register_fn("shout", shout).unwrap();
+    vm.register_fn("halve", halve).unwrap();
+    vm.register_fn("checked_div", checked_div).unwrap();
+
+    (engine, vm)
+}
+
+/// Evaluate a program in both backends
+fn run(source: &str) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let (mut engine, mut vm) = backends();
+
+    (engine.eval(source), vm.eval(source))
+}
+
+#[test]
+fn arguments_are_converted_for_the_host() {
+
+    let cases: &[(&str, Value)] = &[
+        ("#add(2, 3) * 2",              Value::from(10)),
+        ("#add(1 + 1, #add(1, 2))",     Value::from(5)),
+        ("#shout('hi')",                Value::from("HI")),
+        ("#halve(3)",                   Value::from(1.5)),
+        ("#halve(1r / 2)",              Value::from(0.25)),
+        ("#checked_div(7, 2)",          Value::from(3)),
+    ];
+
+    for (source, expected) in cases {
+
+        let (from_engine, from_vm) = run(source);
+
+        assert_eq!(from_engine.unwrap(), Some(expected.clone()), "engine: {}", source);
+        assert_eq!(from_vm.unwrap(), Some(expected.clone()), "vm: {}", source);
+    }
+}
+
+#[test]
+fn arguments_of_the_wrong_type_are_refused() {
+
+    for source in ["#add(1, 'two')", "#add(1.5, 2)", "#shout(5)", "#halve('1')", "#add(2 ** 64, 1)"].iter() {
+
+        let (from_engine, from_vm) = run(source);
+
+        assert!(matches!(from_engine, Err(EvalError::ExecutionError(ExecutionError::ConversionFailure(_, _)))), "engine: {} gave {:?}", source, from_engine);
+        assert!(matches!(from_vm, Err(EvalError::ExecutionError(ExecutionError::ConversionFailure(_, _)))), "vm: {} gave {:?}", source, from_vm);
+    }
+}
+
+#[test]
+fn calls_with_the_wrong_number_of_arguments_are_refused() {
+
+    for source in ["#add(1)", "#add(1, 2, 3)", "#shout()"].iter() {
+
+        let (from_engine, from_vm) = run(source);
+
+        assert!(matches!(from_engine, Err(EvalError::ExecutionError(ExecutionError::InvalidParameters))), "engine: {} gave {:?}", source, from_engine);
+        assert!(matches!(from_vm, Err(EvalError::ExecutionError(ExecutionError::InvalidParameters))), "vm: {} gave {:?}", source, from_vm);
+    }
+}
+
+#[test]
+fn errors_from_the_host_reach_the_script() {
+
+    let (from_engine, from_vm) = run("#checked_div(1, 0)");
+
+    assert!(matches!(from_engine, Err(EvalError::ExecutionError(ExecutionError::DivisionByZero))), "engine gave {:?}", from_engine);
+    assert!(matches!(from_vm, Err(EvalError::ExecutionError(ExecutionError::DivisionByZero))), "vm gave {:?}", from_vm);
+}
+
+#[test]
+fn functions_that_return_nothing_leave_nothing() {
+
+    let seen = Rc::new(RefCell::new(Vec::new()));
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    let log = seen.clone();
+    engine.register_fn("log", move |text: String| log.borrow_mut().push(text)).unwrap();
+
+    let log = seen.clone();
+    vm.register_fn("log", move |text: String| log.borrow_mut().push(text)).unwrap();
+
+    assert_eq!(engine.eval("#log('engine')").unwrap(), None);
+    assert_eq!(vm.eval("#log('vm')").unwrap(), None);
+
+    // Assigning the result of the call gives nil
+    assert_eq!(engine.eval("r = #log('again')  r == nil").unwrap(), Some(Value::from(true)));
+    assert_eq!(vm.eval("r = #log('again')  r == nil").unwrap(), Some(Value::from(true)));
+
+    assert_eq!(*seen.borrow(), vec!["engine", "vm", "again", "again"]);
+}
+
+#[test]
+fn names_of_built_in_functions_are_refused() {
+
+    for name in ["sqrt", "pi", "max", "drop", "precision", "auto_vivify"].iter() {
+
+        let mut engine = Engine::new();
+        let mut vm = Vm::new();
+
+        let from_engine = engine.register_fn(name, |n: i64| n);
+        let from_vm = vm.register_fn(name, |n: i64| n);
+
+        assert!(matches!(&from_engine, Err(ExecutionError::ReservedFunctionName(reserved)) if reserved == name), "engine: {} gave {:?}", name, from_engine);
+        assert!(matches!(&from_vm, Err(ExecutionError::ReservedFunctionName(reserved)) if reserved == name), "vm: {} gave {:?}", name, from_vm);
+    }
+}
+
+#[test]
+fn unknown_functions_are_named() {
+
+    let (from_engine, from_vm) = run("#missing(1)");
+
+    assert!(matches!(&from_engine, Err(EvalError::ExecutionError(ExecutionError::UnknownBuiltInFunction(name))) if name == "missing"), "engine gave {:?}", from_engine);
+    assert!(matches!(&from_vm, Err(EvalError::ExecutionError(ExecutionError::UnknownBuiltInFunction(name))) if name == "missing"), "vm gave {:?}", from_vm);
+}
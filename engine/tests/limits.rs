@@ -0,0 +1,73 @@
+/*
+
+    Pins down how integer operations behave at the edges of what they can build. Every case
+    is run through both the tree walking engine and the virtual machine
+*/
+
+use micron_engine::{ Engine, Vm, Value, EvalError, ExecutionError };
+
+/// Shifts right by amounts too large to hold in a machine word still give a value
+const SHIFT_CASES: &[(&str, i64)] = &[
+    ("12 >> 2",             3),
+    ("5 >> 2 ** 32",        0),
+    ("-5 >> 2 ** 32",       -1),
+    ("5 >> 2 ** 70",        0),
+    ("-5 >> 2 ** 70",       -1),
+    ("(2 ** 100) >> 98",    4),
+];
+
+/// Programs that have to be stopped for building an integer that is too large
+const OVERFLOW_CASES: &[(&str, &str)] = &[
+    ("x = 3\nwhile true { x = x * x }",   "mul"),
+    ("x = 2 ** 40000000",                 "pow"),
+    ("x = 1 << 2 ** 30",                  "lsh"),
+];
+
+/// Evaluate a program in both backends
+fn run(source: &str) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    (engine.eval(source), vm.eval(source))
+}
+
+#[test]
+fn large_right_shifts_run_out_of_bits() {
+
+    for (source, expected) in SHIFT_CASES {
+
+        let program = format!("result = {}\nresult", source);
+        let (from_engine, from_vm) = run(&program);
+
+        assert_eq!(from_engine.unwrap(), Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(from_vm.unwrap(), Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
+
+#[test]
+fn negative_shifts_are_refused() {
+
+    let (from_engine, from_vm) = run("result = 8 >> -1");
+
+    assert!(matches!(from_engine, Err(EvalError::ExecutionError(ExecutionError::ShiftOutOfRange(_)))), "engine gave {:?}", from_engine);
+    assert!(matches!(from_vm, Err(EvalError::ExecutionError(ExecutionError::ShiftOutOfRange(_)))), "vm gave {:?}", from_vm);
+}
+
+#[test]
+fn integers_that_grow_too_large_overflow() {
+
+    for (source, operation) in OVERFLOW_CASES {
+
+        let (from_engine, from_vm) = run(source);
+
+        for result in [from_engine, from_vm].iter() {
+            match result {
+                Err(EvalError::ExecutionError(ExecutionError::Overflow(op))) => {
+                    assert_eq!(op, operation, "{}", source);
+                }
+                other => { panic!("'{}' gave {:?}", source, other); }
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+/*
+
+    Pins down that && and || only evaluate their right side when the left side doesn't already
+    decide the result. Every case is run through both the tree walking engine and the virtual machine
+*/
+
+use micron_engine::{ Engine, Vm, Value, EvalError, ExecutionError };
+
+/// Cases whose right side would fail if it were evaluated
+const SKIPPED_CASES: &[(&str, bool)] = &[
+    ("false && 1/0",                false),
+    ("true || 1/0",                 true),
+    ("0 && 1/0",                    false),
+    ("1 || 1/0",                    true),
+    ("nil && 1 % 0",                false),
+    ("1 > 2 && 1 << -1",            false),
+    ("false && 1/0 || true",        true),
+    ("true || 1/0 && 1/0",          true),
+];
+
+/// Evaluate a program in both backends
+fn run(source: &str) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    (engine.eval(source), vm.eval(source))
+}
+
+#[test]
+fn decided_logic_skips_the_right_side() {
+
+    for (source, expected) in SKIPPED_CASES {
+
+        let program = format!("result = {}\nresult", source);
+        let (from_engine, from_vm) = run(&program);
+
+        assert_eq!(from_engine.unwrap(), Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(from_vm.unwrap(), Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
+
+#[test]
+fn guards_keep_an_index_in_bounds() {
+
+    let source = "l = []\nresult = l.len() > 0 && l[0]\nresult";
+    let (from_engine, from_vm) = run(source);
+
+    assert_eq!(from_engine.unwrap(), Some(Value::from(false)));
+    assert_eq!(from_vm.unwrap(), Some(Value::from(false)));
+}
+
+#[test]
+fn right_side_runs_only_when_needed() {
+
+    // Each call that runs leaves a mark in the list
+    let source = "fn touch(l) { l.push(1)  return true }
+        l = []
+        r = false && touch(l)
+        s = true || touch(l)
+        t = true && touch(l)
+        u = false || touch(l)
+        l.len()";
+
+    let (from_engine, from_vm) = run(source);
+
+    assert_eq!(from_engine.unwrap(), Some(Value::from(2)));
+    assert_eq!(from_vm.unwrap(), Some(Value::from(2)));
+}
+
+#[test]
+fn undecided_logic_evaluates_the_right_side() {
+
+    for source in ["result = true && 1/0", "result = false || 1/0"].iter() {
+
+        let (from_engine, from_vm) = run(source);
+
+        assert!(matches!(from_engine, Err(EvalError::ExecutionError(ExecutionError::DivisionByZero))), "engine: {}", source);
+        assert!(matches!(from_vm, Err(EvalError::ExecutionError(ExecutionError::DivisionByZero))), "vm: {}", source);
+    }
+}
@@ -0,0 +1,237 @@
+/*
+
+    Pins down rationals, complex numbers, precision and the math library. Every case is run
+    through both the tree walking engine and the virtual machine
+*/
+
+use micron_engine::{ Engine, Vm, Value, EvalError, ExecutionError };
+use rug::{ Integer, Float, Rational, Complex };
+
+/// Precision, in bits, of an engine that hasn't been told otherwise
+const DEFAULT_PRECISION: u32 = 53;
+
+fn rational(numer: i64, denom: i64) -> Value {
+    Value::from(Rational::from((numer, denom)))
+}
+
+fn complex(re: f64, im: f64) -> Value {
+    Value::from(Complex::with_val(DEFAULT_PRECISION, (re, im)))
+}
+
+/// Evaluate a program in both backends
+fn run(source: &str) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    (engine.eval(source), vm.eval(source))
+}
+
+/// Evaluate an expression in both backends and check what it gives
+fn check(source: &str, expected: &Value) {
+    gives(&format!("result = {}\nresult", source), expected);
+}
+
+/// Evaluate a program in both backends and check what it gives
+fn gives(source: &str, expected: &Value) {
+
+    let (from_engine, from_vm) = run(source);
+
+    assert_eq!(from_engine.unwrap_or_else(|e| panic!("engine failed on '{}' : {:?}", source, e)), Some(expected.clone()), "engine: {}", source);
+    assert_eq!(from_vm.unwrap_or_else(|e| panic!("vm failed on '{}' : {:?}", source, e)), Some(expected.clone()), "vm: {}", source);
+}
+
+/// Evaluate a program in both backends and check that it fails the way it should
+fn refused(source: &str, expected: fn(&ExecutionError) -> bool) {
+
+    let (from_engine, from_vm) = run(source);
+
+    for result in [from_engine, from_vm].iter() {
+        match result {
+            Err(EvalError::ExecutionError(e)) if expected(e) => { }
+            other => { panic!("'{}' gave {:?}", source, other); }
+        }
+    }
+}
+
+/// Get the precision of the float a program results in, from both backends
+fn precision_of(source: &str) -> (u32, u32) {
+
+    let precision = |result: Result<Option<Value>, EvalError>| {
+        match result {
+            Ok(Some(Value::Float(f))) => { f.prec() }
+            other => { panic!("'{}' gave {:?}", source, other); }
+        }
+    };
+
+    let (from_engine, from_vm) = run(source);
+
+    (precision(from_engine), precision(from_vm))
+}
+
+#[test]
+fn rationals_are_exact_and_kept_in_lowest_terms() {
+
+    let cases: &[(&str, Value)] = &[
+        ("1r / 3 + 1r / 6",         rational(1, 2)),
+        ("6r / 4",                  rational(3, 2)),
+        ("1r / 3 * 3",              rational(1, 1)),
+        ("-2r / 4",                 rational(-1, 2)),
+        ("1r / 2 - 1",              rational(-1, 2)),
+        ("(2r / 3) ** 2",           rational(4, 9)),
+        ("(2r / 3) ** -2",          rational(9, 4)),
+        ("7r / 2 % 2",              rational(3, 2)),
+        ("(3r / 4).numer()",        Value::from(3)),
+        ("(6r / 8).denom()",        Value::from(4)),
+        ("(1r / 4).as_float()",     Value::from(0.25)),
+        ("1r / 2 + 0.25",           Value::from(0.75)),
+        ("1 / 2",                   Value::from(0)),
+    ];
+
+    for (source, expected) in cases {
+        check(source, expected);
+    }
+
+    refused("result = 1r / 0", |e| matches!(e, ExecutionError::DivisionByZero));
+    refused("result = 1r / 2 % 0", |e| matches!(e, ExecutionError::DivisionByZero));
+}
+
+#[test]
+fn complex_numbers_take_over_other_numbers() {
+
+    let cases: &[(&str, Value)] = &[
+        ("3i",                      complex(0.0, 3.0)),
+        ("2.5i",                    complex(0.0, 2.5)),
+        ("1 + 2i",                  complex(1.0, 2.0)),
+        ("(1 + 2i) * (3 - 1i)",     complex(5.0, 5.0)),
+        ("2i ** 2",                 complex(-4.0, 0.0)),
+        ("(1 + 1i) / 2",            complex(0.5, 0.5)),
+        ("1r / 2 + 1i",             complex(0.5, 1.0)),
+        ("(1 + 2i).conj()",         complex(1.0, -2.0)),
+        ("-(1 + 2i)",               complex(-1.0, -2.0)),
+        ("(3 + 4i).re()",           Value::from(3.0)),
+        ("(3 + 4i).im()",           Value::from(4.0)),
+        ("(3 + 4i).abs()",          Value::from(5.0)),
+        ("(0 + 1i).arg() * 2 == #pi()", Value::from(true)),
+    ];
+
+    for (source, expected) in cases {
+        check(source, expected);
+    }
+
+    refused("result = 1i / 0", |e| matches!(e, ExecutionError::DivisionByZero));
+    refused("result = 1i & 1", |e| matches!(e, ExecutionError::InvalidOperation(_)));
+}
+
+#[test]
+fn precision_is_set_for_the_engine_and_for_single_floats() {
+
+    assert_eq!(precision_of("1.5"), (DEFAULT_PRECISION, DEFAULT_PRECISION));
+    assert_eq!(precision_of("#precision(100)\n1.5"), (100, 100));
+    assert_eq!(precision_of("#precision(100)\n(1r / 3).as_float()"), (100, 100));
+    assert_eq!(precision_of("#precision(80)\n#pi()"), (80, 80));
+    assert_eq!(precision_of("(1.5).with_precision(10)"), (10, 10));
+    assert_eq!(precision_of("x = 1.5\nx.set_precision(200)\nx"), (200, 200));
+
+    // Floats that were read in keep their precision when the engine's changes
+    assert_eq!(precision_of("x = 1.5\n#precision(100)\nx"), (DEFAULT_PRECISION, DEFAULT_PRECISION));
+
+    gives("#precision(64)\n#precision()", &Value::from(64));
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    engine.set_precision(128).unwrap();
+    vm.set_precision(128).unwrap();
+
+    assert_eq!(engine.eval("#precision()").unwrap(), Some(Value::from(128)));
+    assert_eq!(vm.eval("#precision()").unwrap(), Some(Value::from(128)));
+
+    assert!(engine.set_precision(0).is_err());
+    assert!(vm.set_precision(0).is_err());
+}
+
+#[test]
+fn precision_out_of_range_is_refused() {
+
+    for source in ["#precision(0)", "#precision(-1)", "#precision(2 ** 27)", "#precision(1.5)", "result = (1.5).with_precision(0)"].iter() {
+        refused(source, |e| matches!(e, ExecutionError::InvalidOperation(_)));
+    }
+
+    refused("#precision(1, 2)", |e| matches!(e, ExecutionError::InvalidParameters));
+}
+
+#[test]
+fn math_library_functions() {
+
+    let cases: &[(&str, Value)] = &[
+        ("#sqrt(16)",               Value::from(4.0)),
+        ("#sqrt(-4 + 0i)",          complex(0.0, 2.0)),
+        ("#ln(1)",                  Value::from(0.0)),
+        ("#exp(0)",                 Value::from(1.0)),
+        ("#log10(1000)",            Value::from(3.0)),
+        ("#sin(0)",                 Value::from(0.0)),
+        ("#cos(0)",                 Value::from(1.0)),
+        ("#atan(0)",                Value::from(0.0)),
+        ("#floor(2.7)",             Value::from(2)),
+        ("#ceil(2.1)",              Value::from(3)),
+        ("#round(-2.5)",            Value::from(-3)),
+        ("#trunc(7r / 2)",          Value::from(3)),
+        ("#floor(5)",               Value::from(5)),
+        ("#abs(-3)",                Value::from(3)),
+        ("#abs(-1r / 2)",           rational(1, 2)),
+        ("#abs(-1.5)",              Value::from(1.5)),
+        ("#min(3, 1r / 2, 2.5)",    rational(1, 2)),
+        ("#max(3, 1r / 2, 2.5)",    Value::from(3)),
+        ("#gcd(12, 18, 8)",         Value::from(2)),
+        ("#gcd(-4, 6)",             Value::from(2)),
+        ("#lcm(4, 6)",              Value::from(12)),
+        ("#factorial(0)",           Value::from(1)),
+        ("#factorial(20)",          Value::from(Integer::from(2432902008176640000_u64))),
+        ("#is_prime(97)",           Value::from(true)),
+        ("#is_prime(91)",           Value::from(false)),
+        ("#next_prime(14)",         Value::from(17)),
+        ("#pi()",                   Value::from(Float::with_val(DEFAULT_PRECISION, rug::float::Constant::Pi))),
+        ("#e() == #exp(1)",         Value::from(true)),
+    ];
+
+    for (source, expected) in cases {
+        check(source, expected);
+    }
+}
+
+#[test]
+fn math_outside_the_domain() {
+
+    // Real functions give NaN rather than an error, which is never equal to itself
+    gives("x = #sqrt(-4)\nx == x", &Value::from(false));
+    gives("x = #acos(2)\nx == x", &Value::from(false));
+
+    refused("result = #factorial(-1)", |e| matches!(e, ExecutionError::InvalidOperation(_)));
+    refused("result = #factorial(2 ** 40)", |e| matches!(e, ExecutionError::Overflow(_)));
+    refused("result = #floor(#sqrt(-1))", |e| matches!(e, ExecutionError::NotFinite(_)));
+    refused("result = #gcd(1.5, 2)", |e| matches!(e, ExecutionError::ConversionFailure(_, _)));
+    refused("result = #sqrt('4')", |e| matches!(e, ExecutionError::ConversionFailure(_, _)));
+    refused("result = #floor(1i)", |e| matches!(e, ExecutionError::InvalidOperation(_)));
+}
+
+#[test]
+fn math_with_the_wrong_number_of_arguments() {
+
+    for source in ["#sqrt()", "#sqrt(1, 2)", "#pi(1)", "#gcd()", "#factorial(1, 2)"].iter() {
+        refused(source, |e| matches!(e, ExecutionError::InvalidParameters));
+    }
+}
+
+#[test]
+fn math_functions_can_not_be_replaced_by_the_host() {
+
+    for name in ["sqrt", "ln", "floor", "min", "gcd", "is_prime", "pi", "e"].iter() {
+
+        let mut engine = Engine::new();
+        let mut vm = Vm::new();
+
+        assert!(matches!(engine.register_fn(name, |n: i64| n), Err(ExecutionError::ReservedFunctionName(_))), "engine: {}", name);
+        assert!(matches!(vm.register_fn(name, |n: i64| n), Err(ExecutionError::ReservedFunctionName(_))), "vm: {}", name);
+    }
+}
@@ -0,0 +1,167 @@
+/*
+
+    Pins down how operators group. Every case is run through both the tree walking engine 
+    and the virtual machine, and has to come out the same in each
+*/
+
+use micron_engine::{ Engine, Vm, Value };
+
+/// Source to evaluate and what it should evaluate to
+const CASES: &[(&str, i64)] = &[
+
+    // Multiplicative over additive
+    ("1 + 2 * 3",       7),
+    ("(1 + 2) * 3",     9),
+    ("10 - 4 / 2",      8),
+    ("7 + 10 % 4",      9),
+
+    // Left associativity
+    ("10 - 4 - 3",      3),
+    ("100 / 10 / 5",    2),
+    ("2 * 7 % 4",       2),
+
+    // Power binds tightest and groups to the right
+    ("2 ** 3 ** 2",     512),
+    ("(2 ** 3) ** 2",   64),
+    ("2 * 3 ** 2",      18),
+    ("-2 ** 2",         -4),
+    ("(-2) ** 2",       4),
+    ("2 ** -0",         1),
+    ("0 ** 0",          1),
+    ("0 ** 3",          0),
+    ("(-1) ** 0",       1),
+    ("(-1) ** 3",       -1),
+
+    // Unary minus
+    ("-3 + 5",          2),
+    ("5 - -3",          8),
+    ("5--3",            8),
+    ("- - 4",           4),
+    ("-(1 + 2) * 3",    -9),
+    ("1-2",             -1),
+    ("(0-8)",           -8),
+
+    // Shifts below additive
+    ("1 + 2 << 3",      24),
+    ("1 << 2 + 1",      8),
+    ("64 >> 1 + 1",     16),
+    ("1 << 4 >> 2",     4),
+
+    // Bitwise operations below comparisons, and & over ^ over |
+    ("1 | 2 ^ 3 & 1",   3),
+    ("6 & 3 | 8",       10),
+    ("5 ^ 1 | 2",       6),
+    ("12 & 10 ^ 6",     14),
+    ("~0 & 7",          7),
+    ("~1 + 1",          -1),
+];
+
+/// Cases that result in a bool
+const BOOL_CASES: &[(&str, bool)] = &[
+
+    // Comparisons below shifts and arithmetic
+    ("1 + 1 == 2",          true),
+    ("1 << 2 > 3",          true),
+    ("2 * 3 >= 6",          true),
+    ("3 != 1 + 2",          false),
+    ("1 < 2 == 2 < 3",      true),
+    ("1 != 2",              true),
+    ("2 != 2",              false),
+
+    // Logic operations bind the loosest, && over ||
+    ("1 || 0 && 0",         true),
+    ("(1 || 0) && 0",       false),
+    ("0 && 1 || 1",         true),
+    ("1 == 1 && 2 > 1",     true),
+    ("0 || 2 * 0",          false),
+    ("1 | 0 && 2 & 0",      false),
+
+    // Not binds tighter than every binary operation
+    ("!0 == true",          true),
+    ("!1 || 1",             true),
+    ("!(1 || 1)",           false),
+];
+
+/// Evaluate an expression in both backends. It is assigned first since a statement can't lead with '-'
+fn evaluate(source: &str) -> (Option<Value>, Option<Value>) {
+
+    let program = format!("result = {}\nresult", source);
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    let from_engine = engine.eval(&program).unwrap_or_else(|e| panic!("engine failed on '{}' : {:?}", source, e));
+    let from_vm = vm.eval(&program).unwrap_or_else(|e| panic!("vm failed on '{}' : {:?}", source, e));
+
+    (from_engine, from_vm)
+}
+
+#[test]
+fn arithmetic_and_bitwise_precedence() {
+
+    for (source, expected) in CASES {
+
+        let (from_engine, from_vm) = evaluate(source);
+
+        assert_eq!(from_engine, Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(from_vm, Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
+
+#[test]
+fn comparison_and_logic_precedence() {
+
+    for (source, expected) in BOOL_CASES {
+
+        let (from_engine, from_vm) = evaluate(source);
+
+        assert_eq!(from_engine, Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(from_vm, Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
+
+#[test]
+fn minus_can_not_start_a_statement() {
+
+    // Statements aren't separated, so a leading '-' continues the statement before it
+    let mut vm = Vm::new();
+
+    assert_eq!(vm.eval("a = 5\n-2 + 1").unwrap(), None);
+    assert_eq!(vm.get_global("a"), Some(Value::from(4)));
+}
+
+#[test]
+fn comments_take_up_the_whole_line() {
+
+    let source = "-- leading comment\na = 5--3\n    -- indented comment\nb = a -- 1\nb";
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    assert_eq!(engine.eval(source).unwrap(), Some(Value::from(9)));
+    assert_eq!(vm.eval(source).unwrap(), Some(Value::from(9)));
+}
+
+#[test]
+fn parentheses_and_brackets_can_start_a_statement() {
+
+    // A '(' or '[' that starts a line never calls or indexes the line before it
+    let cases: &[(&str, i64)] = &[
+        ("(1 + 2) * 3",                             9),
+        ("a = 1\n(a + 2) * 3",                      9),
+        ("l = [5]\n[1, 2].len()",                   2),
+        ("fn f(n) { return n }\nx = f\n(4)",         4),
+        ("if true { (1 + 1) * 3 }",                 6),
+        ("fn f(a, b) { return a * b }\nf(\n  (1 + 1),\n  3)", 6),
+        ("l = [\n  [1, 2],\n  [3]\n]\nl[0][1] + l[1][0]", 5),
+    ];
+
+    for (source, expected) in cases {
+
+        let mut engine = Engine::new();
+        let mut vm = Vm::new();
+
+        assert_eq!(engine.eval(source).unwrap(), Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(vm.eval(source).unwrap(), Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
@@ -0,0 +1,120 @@
+/*
+
+    Pins down when dictionaries and lists are shared and when they are copied. Dictionaries and
+    lists are handles, so assigning one, handing one to a function or yielding one shares it.
+    Everything else is a value. Every case is run through both the tree walking engine and
+    the virtual machine, and has to come out the same in each
+*/
+
+use micron_engine::{ Engine, Vm, Value, Key, EvalError, ExecutionError };
+
+/// Source to run and what its final expression should evaluate to
+const CASES: &[(&str, i64)] = &[
+
+    // Assignment shares the container, changes through either name are seen by both
+    ("a = { 'k' : 0 }  b = a  b['k'] = 1  b['n'] = 2  a['k'] + a['n']",            3),
+    ("a = [1]  b = a  b.push(2)  b[0] = 5  a.len() * 10 + a[0]",                   25),
+
+    // Containers read out of a container are shared with it
+    ("d = { 'l' : [] }  inner = d['l']  inner.push(1)  d['l'].len()",               1),
+    ("l = [{ 'x' : 1 }]  first = l[0]  first['x'] = 7  l[0]['x']",                  7),
+    ("d = { 'l' : [] }  v = d.values()  v[0].push(1)  d['l'].len()",                1),
+    ("d = { 'l' : [] }  for k, v in d { v.push(1) }  d['l'].len()",                 1),
+    ("l = [[], []]  for item in l { item.push(1) }  l[0].len() + l[1].len()",       2),
+
+    // Functions see the caller's container, but giving the parameter a new value doesn't reach it
+    ("fn f(m) { m['x'] = 1 }  d = {}  f(d)  d['x']",                                1),
+    ("fn f(m) { m = { 'x' : 2 } }  d = { 'x' : 1 }  f(d)  d['x']",                  1),
+    ("fn f(l) { l.push(1) }  l = []  f(l)  f(l)  l.len()",                          2),
+
+    // Yield hands out the same container
+    ("l = []  r = if true { yield l }  r.push(1)  l.len()",                         1),
+    ("fn make(l) { yield l }  l = []  make(l).push(1)  l.len()",                    1),
+
+    // Everything that isn't a dictionary or list is a value
+    ("x = 5  y = x  y = 6  x",                                                      5),
+    ("d = { 'k' : 1 }  v = d['k']  v = 2  d['k']",                                  1),
+    ("fn f(n) { n = n + 1 }  n = 1  f(n)  n",                                       1),
+
+    // A copy has keys of its own, but shares the containers within it
+    ("a = { 'k' : 0 }  c = a.copy()  c['k'] = 1  c['n'] = 2  a['k'] * 10 + a.len()", 1),
+    ("a = { 'n' : [] }  c = a.copy()  c['n'].push(1)  a['n'].len()",                1),
+    ("a = [1, []]  c = a.copy()  c.push(3)  c[1].push(1)  a.len() * 10 + a[1].len()", 21),
+
+    // A deep copy shares nothing
+    ("a = { 'n' : { 'x' : 1 } }  c = a.deep_copy()  c['n']['x'] = 2  a['n']['x']", 1),
+    ("a = [[1]]  c = a.deep_copy()  c[0].push(2)  a[0].len()",                      1),
+    ("i = []  a = [i, i]  c = a.deep_copy()  c[0].push(1)  c[1].len()",             0),
+
+    // Copying a value gives the same value
+    ("x = 3  x.copy() + x.deep_copy()",                                             6),
+
+    // Merging a dictionary into itself leaves it as it is
+    ("a = { 'x' : 1 }  a.merge(a)  a.len()",                                        1),
+];
+
+/// Sources that would put a container inside of itself
+const CYCLES: &[&str] = &[
+    "a = {}  a['self'] = a",
+    "a = { 'i' : {} }  a['i']['up'] = a",
+    "a = {}  b = {}  a['b'] = b  b['a'] = a",
+    "l = []  l.push(l)",
+    "l = [[]]  l[0].push(l)",
+    "l = [1]  l.insert(0, l)",
+    "l = [1]  l[0] = l",
+    "a = {}  b = { 'x' : a }  a.merge(b)",
+    "d = { 'a' : {} }  d['a'].merge(d)",
+];
+
+/// Run a program in both backends and hand back what each gives
+fn run(source: &str) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    (engine.eval(source), vm.eval(source))
+}
+
+#[test]
+fn containers_are_shared_and_values_are_not() {
+
+    for (source, expected) in CASES {
+
+        let (from_engine, from_vm) = run(source);
+
+        let from_engine = from_engine.unwrap_or_else(|e| panic!("engine failed on '{}' : {:?}", source, e));
+        let from_vm = from_vm.unwrap_or_else(|e| panic!("vm failed on '{}' : {:?}", source, e));
+
+        assert_eq!(from_engine, Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(from_vm, Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
+
+#[test]
+fn containers_can_not_hold_themselves() {
+
+    for source in CYCLES {
+
+        let (from_engine, from_vm) = run(source);
+
+        assert!(refused(&from_engine), "engine should refuse '{}', gave {:?}", source, from_engine);
+        assert!(refused(&from_vm), "vm should refuse '{}', gave {:?}", source, from_vm);
+    }
+}
+
+/// Check that a program was stopped by an invalid operation
+fn refused(result: &Result<Option<Value>, EvalError>) -> bool {
+    matches!(result, Err(EvalError::ExecutionError(ExecutionError::InvalidOperation(_))))
+}
+
+#[test]
+fn values_handed_to_the_host_are_copies() {
+
+    let mut vm = Vm::new();
+
+    let before = vm.eval("d = { 'l' : [1] }  d").unwrap();
+    vm.eval("d['l'].push(2)").unwrap();
+
+    let expected = Value::Dict(vec![(Key::from("l"), Value::from(vec![Value::from(1)]))].into_iter().collect());
+    assert_eq!(before, Some(expected));
+}
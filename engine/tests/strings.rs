@@ -0,0 +1,169 @@
+/*
+
+    Pins down string methods, the escapes decoded within strings and how interpolated strings
+    are formatted. Every case is run through both the tree walking engine and the virtual machine
+*/
+
+use micron_engine::{ Engine, Vm, Value, EvalError, ExecutionError };
+
+/// String methods, which count in characters rather than bytes
+const METHOD_CASES: &[(&str, &str)] = &[
+    ("'héllo'.at(1)",                   "é"),
+    ("'héllo'.slice(1, 3)",             "él"),
+    ("'héllo'.slice(2)",                "llo"),
+    ("'aXbX'.replace('X', '-')",        "a-b-"),
+    ("'ab'.repeat(3)",                  "ababab"),
+    ("'ab'.repeat(0)",                  ""),
+    ("'  x \\t'.trim()",                "x"),
+    ("'Ab'.upper()",                    "AB"),
+    ("'Ab'.lower()",                    "ab"),
+    ("','.join(['a', 'b', 'c'])",       "a,b,c"),
+    ("'-'.join('héllo'.chars())",       "h-é-l-l-o"),
+    ("','.join('a b  c'.split())",      "a,b,c"),
+    ("'|'.join('a,b,,c'.split(','))",   "a|b||c"),
+];
+
+/// Escapes decoded within strings, and raw strings that are kept as written
+const ESCAPE_CASES: &[(&str, &str)] = &[
+    (r#""a\nb""#,                       "a\nb"),
+    (r#""\t\r\0""#,                     "\t\r\0"),
+    (r#""\\ \" \'""#,                   "\\ \" '"),
+    (r#"'\'quoted\''"#,                 "'quoted'"),
+    (r#""\u{2603}\u{41}""#,             "\u{2603}A"),
+    (r#"r"C:\new\folder""#,             "C:\\new\\folder"),
+    (r#"r'say "hi"'"#,                  "say \"hi\""),
+];
+
+/// Interpolated strings and the format specs within them
+const FORMAT_CASES: &[(&str, &str)] = &[
+    ("f\"{1 + 2} and {'x'}\"",          "3 and x"),
+    ("f\"{{}} {{{7}}}\"",               "{} {7}"),
+    ("f\"[{5:4}]\"",                    "[   5]"),
+    ("f\"[{'ab':4}]\"",                 "[ab  ]"),
+    ("f\"[{5:<4}]\"",                   "[5   ]"),
+    ("f\"[{'ab':>4}]\"",                "[  ab]"),
+    ("f\"[{'ab':^6}]\"",                "[  ab  ]"),
+    ("f\"[{'ab':^5}]\"",                "[ ab  ]"),
+    ("f\"[{7:*<4}]\"",                  "[7***]"),
+    ("f\"[{7:é^3}]\"",                  "[é7é]"),
+    ("f\"{-5:05}\"",                    "-0005"),
+    ("f\"{5:08b}\"",                    "00000101"),
+    ("f\"{255:x} {255:X} {8:o}\"",      "ff FF 10"),
+    ("f\"{-255:x}\"",                   "-ff"),
+    ("f\"{3.14159:.2}\"",               "3.14"),
+    ("f\"{2.5:.0} {-2.5:.0}\"",         "3 -3"),
+    ("f\"{1r / 3:.3}\"",                "0.333"),
+    ("f\"{7:.2}\"",                     "7.00"),
+    ("f\"{'abc':.2}\"",                 "ab"),
+    ("f\"{1.5:08.2}\"",                 "00001.50"),
+];
+
+/// Evaluate a program in both backends
+fn run(source: &str) -> (Result<Option<Value>, EvalError>, Result<Option<Value>, EvalError>) {
+
+    let mut engine = Engine::new();
+    let mut vm = Vm::new();
+
+    (engine.eval(source), vm.eval(source))
+}
+
+/// Evaluate each expression in both backends and check the string it gives
+fn check_all(cases: &[(&str, &str)]) {
+
+    for (source, expected) in cases {
+
+        let (from_engine, from_vm) = run(&format!("result = {}\nresult", source));
+
+        assert_eq!(from_engine.unwrap_or_else(|e| panic!("engine failed on '{}' : {:?}", source, e)), Some(Value::from(*expected)), "engine: {}", source);
+        assert_eq!(from_vm.unwrap_or_else(|e| panic!("vm failed on '{}' : {:?}", source, e)), Some(Value::from(*expected)), "vm: {}", source);
+    }
+}
+
+/// Evaluate a program in both backends and check that it fails the way it should
+fn refused(source: &str, expected: fn(&EvalError) -> bool) {
+
+    let (from_engine, from_vm) = run(source);
+
+    for result in [from_engine, from_vm].iter() {
+        match result {
+            Err(e) if expected(e) => { }
+            other => { panic!("'{}' gave {:?}", source, other); }
+        }
+    }
+}
+
+#[test]
+fn string_methods() {
+
+    check_all(METHOD_CASES);
+
+    let cases: &[(&str, Value)] = &[
+        ("'héllo'.len()",               Value::from(5)),
+        ("'héllo'.find('l')",           Value::from(2)),
+        ("'hello'.find('z')",           Value::Nil),
+        ("'hello'.contains('ell')",     Value::from(true)),
+        ("'hello'.starts_with('he')",   Value::from(true)),
+        ("'hello'.ends_with('he')",     Value::from(false)),
+        ("'ab'.chars()",                Value::from(vec![Value::from("a"), Value::from("b")])),
+    ];
+
+    for (source, expected) in cases {
+
+        let (from_engine, from_vm) = run(&format!("result = {}\nresult", source));
+
+        assert_eq!(from_engine.unwrap(), Some(expected.clone()), "engine: {}", source);
+        assert_eq!(from_vm.unwrap(), Some(expected.clone()), "vm: {}", source);
+    }
+
+    // Methods hand back a new string and leave the one they were called on alone
+    let (from_engine, from_vm) = run("s = 'ab'\nt = s.upper()\ns + t");
+
+    assert_eq!(from_engine.unwrap(), Some(Value::from("abAB")));
+    assert_eq!(from_vm.unwrap(), Some(Value::from("abAB")));
+}
+
+#[test]
+fn string_methods_that_are_refused() {
+
+    refused("result = 'ab'.repeat(-1)", |e| matches!(e, EvalError::ExecutionError(ExecutionError::InvalidOperation(_))));
+    refused("result = 'ab'.repeat(2 ** 40)", |e| matches!(e, EvalError::ExecutionError(ExecutionError::Overflow(_))));
+    refused("result = 'ab'.repeat('2')", |e| matches!(e, EvalError::ExecutionError(ExecutionError::InvalidOperation(_))));
+    refused("result = 'ab'.at(2)", |e| matches!(e, EvalError::ExecutionError(ExecutionError::IndexError)));
+    refused("result = 'ab'.slice(2, 1)", |e| matches!(e, EvalError::ExecutionError(ExecutionError::IndexError)));
+    refused("result = 'ab'.replace('', 'x')", |e| matches!(e, EvalError::ExecutionError(ExecutionError::InvalidOperation(_))));
+    refused("result = 'ab'.upper(1)", |e| matches!(e, EvalError::ExecutionError(ExecutionError::InvalidParameters)));
+    refused("result = 'ab'.nope()", |e| matches!(e, EvalError::ExecutionError(ExecutionError::UnknownVariableMethod(_, _))));
+}
+
+#[test]
+fn escapes_are_decoded() {
+    check_all(ESCAPE_CASES);
+}
+
+#[test]
+fn invalid_escapes_are_refused() {
+
+    // Beyond the last unicode character, a surrogate, too many digits and an unknown escape
+    for source in [r#"a = "\u{110000}""#, r#"a = "\u{d800}""#, r#"a = "\u{1234567}""#, r#"a = "\q""#].iter() {
+        refused(source, |e| matches!(e, EvalError::ParseError(_)));
+    }
+}
+
+#[test]
+fn format_specs() {
+    check_all(FORMAT_CASES);
+}
+
+#[test]
+fn bad_format_specs_are_refused() {
+
+    // Specs that can't be read are found when the source is parsed
+    for source in ["a = f\"{1:q}\"", "a = f\"{1:<<<}\"", "a = f\"{1:.}\"", "a = f\"{1\"", "a = f\"{}\"", "a = f\"}\""].iter() {
+        refused(source, |e| matches!(e, EvalError::ParseError(_)));
+    }
+
+    refused("a = f\"{1.5:x}\"", |e| matches!(e, EvalError::ExecutionError(ExecutionError::InvalidOperation(_))));
+    refused("a = f\"{'ab':b}\"", |e| matches!(e, EvalError::ExecutionError(ExecutionError::InvalidOperation(_))));
+    refused("a = f\"{1:100000000000}\"", |e| matches!(e, EvalError::ExecutionError(ExecutionError::Overflow("format"))));
+    refused("a = f\"{1.5:.99999999999}\"", |e| matches!(e, EvalError::ExecutionError(ExecutionError::Overflow("format"))));
+}
@@ -1,24 +1,166 @@
 
 /*
 
-    Read in a file line by line. Remove all comments and conjoin into single string, or series of parsable strings. 
+    Read in a file and keep its source around so errors can point back into it. 
     Once read in, send to parser. From the parser the statements can be handed to caller to do with as they please
 */
 
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use regex::Regex;
+use std::io::Read;
+use std::fmt;
+
+use lalrpop_util::ParseError;
+use micron_ast::Span;
 
 #[derive(Debug)]
 pub enum ImportError {
     UnableToOpenFile,
     LineReadError,
-    ParseError
+    ParseError(Box<ParseFailure>)
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::UnableToOpenFile => { write!(f, "Unable to open file") }
+            ImportError::LineReadError    => { write!(f, "Unable to read file") }
+            ImportError::ParseError(e)    => { write!(f, "{}", e) }
+        }
+    }
+}
+
+/// Everything known about why a source failed to parse
+#[derive(Debug, Clone)]
+pub struct ParseFailure {
+
+    /// Name of the file (or other origin) the source came from
+    pub file: String,
+
+    /// The token the parser tripped on, None if the source ended early
+    pub found: Option<String>,
+
+    /// Tokens that would have been accepted instead of what was found
+    pub expected: Vec<String>,
+
+    /// Where in the source the failure happened
+    pub location: Span,
+    pub line: usize,
+    pub column: usize,
+
+    /// The full line of source the failure happened on
+    pub source_line: String
+}
+
+impl ParseFailure {
+
+    /// Build a parse failure out of an error from the parser and the source that was being parsed
+    pub fn new<T: fmt::Display, E>(file: &str, source: &str, error: &ParseError<usize, T, E>) -> Self {
+
+        let location = micron_parser::parse_error_span(error);
+
+        let (found, expected) = match error {
+            ParseError::InvalidToken { .. } => {
+                let found = source[location.start..].chars().next().map(|c| c.to_string());
+                (found, Vec::new())
+            }
+            ParseError::UnrecognizedEOF { expected, .. }        => { (None, expected.clone()) }
+            ParseError::UnrecognizedToken { token, expected }   => { (Some(token.1.to_string().trim().to_string()), expected.clone()) }
+            ParseError::ExtraToken { token }                    => { (Some(token.1.to_string().trim().to_string()), Vec::new()) }
+            ParseError::User { .. }                             => { (None, Vec::new()) }
+        };
+
+        let (line, column) = location.line_col(source);
+
+        // Several tokens can share a readable name, only list each name once
+        let mut readable: Vec<String> = Vec::new();
+
+        for token in expected.iter().map(|token| readable_token(token)) {
+            if !readable.contains(&token) {
+                readable.push(token);
+            }
+        }
+
+        ParseFailure {
+            file: file.to_string(),
+            found,
+            expected: readable,
+            location,
+            line,
+            column,
+            source_line: source.lines().nth(line - 1).unwrap_or("").to_string()
+        }
+    }
+}
+
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        match &self.found {
+            Some(found) => { write!(f, "{}:{}:{}: Parse Error : unexpected '{}'", self.file, self.line, self.column, found)?; }
+            None        => { write!(f, "{}:{}:{}: Parse Error : unexpected end of input", self.file, self.line, self.column)?; }
+        }
+
+        if !self.expected.is_empty() {
+            write!(f, ", expected one of {}", self.expected.join(", "))?;
+        }
+
+        // Underline the offending token on the line it came from
+        let gutter = " ".repeat(self.line.to_string().len());
+        let width = std::cmp::max(1, self.found.as_ref().map(|found| found.chars().count()).unwrap_or(1));
+
+        write!(f, "\n{} | {}\n{} | {}{}", self.line, self.source_line, gutter, " ".repeat(self.column - 1), "^".repeat(width))
+    }
+}
+
+/// The parser names tokens by their pattern, which is hard to read for anything
+/// matched by a regex. Swap those out for what they actually are
+fn readable_token(token: &str) -> String {
+
+    if !token.starts_with("r#") {
+        return token.to_string();
+    }
+
+    // A '(' or '[' that starts a line
+    if token.contains("[\\\\n\\\\r]") {
+        match token.ends_with("(\"#") {
+            true  => { return "\"(\"".to_string(); }
+            false => { return "\"[\"".to_string(); }
+        }
+    }
+
+    if token.contains("a-zA-Z") {
+        return "identifier".to_string();
+    }
+
+    // Strings are quoted with either kind of quote, or marked as raw
+    if token.starts_with("r#\"\\\"") || token.starts_with("r#\"'") || token.starts_with("r#\"r") || token.starts_with("r#\"f") {
+        return "string".to_string();
+    }
+
+    if token.contains("]+r") {
+        return "rational".to_string();
+    }
+
+    if token.ends_with("i\"#") {
+        return "imaginary".to_string();
+    }
+
+    // Floats are the only pattern with digits on both sides of something
+    match token.matches("[0-9]+").count() {
+        1 => { return "integer".to_string(); }
+        2 => { return "float".to_string(); }
+        _ => { }
+    }
+
+    token.to_string()
 }
 
 #[derive(Debug)]
 pub struct ImportResult {
-    pub statements: Vec<Box<micron_ast::Statement>>
+    pub statements: Vec<Box<micron_ast::Statement>>,
+
+    /// The source the statements were parsed from, used to show where errors happen
+    pub source: String
 }
 
 pub fn import(file: &str) -> Result<ImportResult, ImportError> {
@@ -34,69 +176,42 @@ pub fn import(file: &str) -> Result<ImportResult, ImportError> {
         of having everything put into one big conjoined string. For big programs that would be awful!
     */
 
-    return simple_importer(file);
+    simple_importer(file)
 }
 
 fn simple_importer(file: &str) -> Result<ImportResult, ImportError> {
 
-    let file_in = match File::open(file) {
+    let mut file_in = match File::open(file) {
         Ok(o) => o,
         Err(_) => return Err(ImportError::UnableToOpenFile)
     };
 
-    // Reader
-    let reader = BufReader::new(file_in);
-
-    // Regex things for detecting things
-    let comment_line = Regex::new(r"^\-{2}[\W+\w+]*").unwrap();
-
-    let mut conjoined_input : String = String::new();
-
-    // Iter over lines and build things
-    for (_index, line) in reader.lines().enumerate() {
-
-        let mut line = match line {
-            Ok(l) => l,
-            Err(_) => return Err(ImportError::LineReadError)
-        };
-
-        // Get rid of all that ws
-        line = line.trim_start().to_string().trim_end().to_string();
-
-        // If there isn't anything on the line, ignore it
-        if line.len() == 0 {
-            continue;
-        }
+    // The whole source is handed to the parser as-is so the locations it records map directly 
+    // back onto the lines of the file. Comments are blanked out and whitespace is dropped by the lexer
+    let mut source = String::new();
 
-        // If there is a comment on the line, then we want to ignore it
-        if comment_line.is_match(line.as_str()) {
-            continue;
-        }
-
-        // Conjoin the new line into the others with a space sep 
-        // This is a bad design decision and should change once things get more solid with the language
-        // This SERIOUSLY needs to be revisited 
-        conjoined_input = conjoined_input + " " + line.as_str();
-
-        //println!("{}. {}", index + 1, line);
+    if file_in.read_to_string(&mut source).is_err() {
+        return Err(ImportError::LineReadError);
     }
 
-    // println!("Result : {}", conjoined_input);
-
-    // Now that we have the giant string of input we feed it to the program parser to generate statements.
+    // Now that we have the source we feed it to the program parser to generate statements.
     // These are the statements that we'll hand back to the caller. When we deserialize things we won't need
     // to do this part, and instead we will just load them up and send them off from the file
-    match micron_parser::micron::ProgramParser::new().parse(&conjoined_input) {
+    let stripped = micron_parser::strip_comments(&source);
+    let result = micron_parser::micron::ProgramParser::new().parse(&stripped);
+
+    match result {
 
         Ok(statements)  => { 
             
-            return Ok(ImportResult{
-                statements: statements
-            });
+            Ok(ImportResult{
+                statements,
+                source
+            })
         }
         
         Err(e) => { 
-            panic!("Error >>> {}", e);
+            Err(ImportError::ParseError(Box::new(ParseFailure::new(file, &source, &e))))
         }
-    };
-}
\ No newline at end of file
+    }
+}
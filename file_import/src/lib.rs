@@ -6,4 +6,5 @@ extern crate micron_parser;
 mod importer;
 pub use importer::import;
 pub use importer::ImportError;
-pub use importer::ImportResult;
\ No newline at end of file
+pub use importer::ImportResult;
+pub use importer::ParseFailure;
\ No newline at end of file
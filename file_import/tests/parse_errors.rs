@@ -0,0 +1,125 @@
+/*
+
+    Pins down what is reported when a file fails to parse. Every case is written out to a file
+    and read back in through the importer, the same way the interpreter reads a program
+*/
+
+use std::fs;
+use std::path::PathBuf;
+
+use micron_file_import::{ import, ImportError, ParseFailure };
+
+/// Tokens that can start an expression, as they are listed when one is missing
+const EXPRESSION_START: &[&str] = &[
+    "\"!\"", "\"#\"", "\"(\"", "\"-\"", "\"[\"", "\"false\"", "\"nil\"", "\"true\"", "\"{\"", "\"~\"",
+    "string", "integer", "imaginary", "float", "rational", "identifier"
+];
+
+/// Write a source out to its own file and import it, handing back why it failed to parse
+fn failure(name: &str, source: &str) -> ParseFailure {
+
+    let path: PathBuf = std::env::temp_dir().join(format!("micron_parse_errors_{}_{}.micron", std::process::id(), name));
+    fs::write(&path, source).unwrap();
+
+    let result = import(path.to_str().unwrap());
+    fs::remove_file(&path).unwrap();
+
+    match result {
+        Err(ImportError::ParseError(failure)) => { *failure }
+        Err(e) => { panic!("'{}' failed to import : {}", source, e) }
+        Ok(_) => { panic!("'{}' parsed", source) }
+    }
+}
+
+/// The lines of the report with the file name taken off the front
+fn report(failure: &ParseFailure) -> Vec<String> {
+
+    let shown = failure.to_string();
+    let prefix = format!("{}:", failure.file);
+
+    shown.lines().map(|line| line.trim_start_matches(prefix.as_str()).to_string()).collect()
+}
+
+#[test]
+fn unexpected_tokens() {
+
+    let failure = failure("token", "a = 1\nb = ) + 2\n");
+
+    assert_eq!((failure.line, failure.column), (2, 5));
+    assert_eq!(failure.found.as_deref(), Some(")"));
+    assert_eq!(failure.source_line, "b = ) + 2");
+    assert!(failure.expected.contains(&"\"if\"".to_string()));
+
+    for token in EXPRESSION_START {
+        assert!(failure.expected.contains(&token.to_string()), "missing {}", token);
+    }
+
+    assert_eq!(report(&failure)[1..], ["2 | b = ) + 2", "  |     ^"]);
+}
+
+#[test]
+fn keywords_are_underlined_in_full() {
+
+    let failure = failure("keyword", "b = 3 + while\n");
+
+    assert_eq!((failure.line, failure.column), (1, 9));
+    assert_eq!(failure.found.as_deref(), Some("while"));
+    assert_eq!(report(&failure), [
+        format!("1:9: Parse Error : unexpected 'while', expected one of {}", EXPRESSION_START.join(", ")),
+        "1 | b = 3 + while".to_string(),
+        "  |         ^^^^^".to_string()
+    ]);
+}
+
+#[test]
+fn unexpected_end_of_input() {
+
+    let failure = failure("end", "x = 1\ny = (2 * 3\n");
+
+    assert_eq!((failure.line, failure.column), (2, 11));
+    assert_eq!(failure.found, None);
+    assert_eq!(failure.expected, ["\")\""]);
+    assert_eq!(report(&failure), [
+        "2:11: Parse Error : unexpected end of input, expected one of \")\"",
+        "2 | y = (2 * 3",
+        "  |           ^"
+    ]);
+
+    // Blank lines at the end of a file don't move the failure past the last line with code on it
+    let failure = self::failure("trailing", "a = 1 +\n\n\n");
+
+    assert_eq!((failure.line, failure.column), (1, 8));
+    assert_eq!(failure.source_line, "a = 1 +");
+}
+
+#[test]
+fn invalid_tokens_list_nothing_expected() {
+
+    let failure = failure("invalid", "x = 5 $ 2\n");
+
+    assert_eq!((failure.line, failure.column), (1, 7));
+    assert_eq!(failure.found.as_deref(), Some("$"));
+    assert!(failure.expected.is_empty());
+    assert_eq!(report(&failure), ["1:7: Parse Error : unexpected '$'", "1 | x = 5 $ 2", "  |       ^"]);
+}
+
+#[test]
+fn columns_count_characters() {
+
+    let failure = failure("characters", "a = \"é\" + )\n");
+
+    assert_eq!((failure.line, failure.column), (1, 11));
+    assert_eq!(report(&failure)[2], "  |           ^");
+}
+
+#[test]
+fn lines_past_nine_widen_the_gutter() {
+
+    let source = format!("{}f(1 2)\n", "a = 1\n".repeat(11));
+    let failure = failure("gutter", &source);
+
+    assert_eq!((failure.line, failure.column), (12, 5));
+    assert_eq!(failure.found.as_deref(), Some("2"));
+    assert_eq!(failure.expected, ["\")\"", "\",\"", "\"]\""]);
+    assert_eq!(report(&failure)[1..], ["12 | f(1 2)", "   |     ^"]);
+}
@@ -0,0 +1,226 @@
+
+/*
+    Interpolated strings are read in by the lexer as a single token, f"total: {x:.2}". Here that token
+    is broken up into the text and the expressions placed within it, each expression is handed back
+    to the parser on its own.
+
+    Expressions are parsed on their own, and the locations recorded within them are then moved
+    forward to where they sit in the file
+*/
+
+use micron_ast::{ Expr, FormatPart, FormatSpec, Alignment };
+
+use crate::micron::ExprParser;
+use crate::{ unescape, parse_error_span };
+
+/// Split an interpolated string token that starts at the given location into its parts.
+/// When something is wrong, the location of the problem is given back instead
+pub fn format_parts(token: &str, location: usize) -> Result<Vec<FormatPart>, usize> {
+
+    // Skip the leading f" and the closing quote
+    let start = location + 2;
+    let body = &token[2..token.len() - 1];
+
+    let chars: Vec<(usize, char)> = body.char_indices().collect();
+
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+
+        let (at, c) = chars[i];
+        let next = chars.get(i + 1).map(|(_, c)| *c);
+
+        match c {
+
+            // Escapes are decoded along with the rest of the text, but '\u{..}' has braces of its
+            // own that must not be taken as the start of an expression
+            '\\' => {
+
+                let end = if next == Some('u') {
+                    chars[i..].iter().position(|(_, c)| *c == '}').map(|p| i + p).unwrap_or(i + 1)
+                } else {
+                    i + 1
+                };
+
+                for (_, c) in &chars[i..=end] {
+                    text.push(*c);
+                }
+                i = end + 1;
+            }
+
+            '{' if next == Some('{') => {
+                text.push('{');
+                i += 2;
+            }
+
+            '}' if next == Some('}') => {
+                text.push('}');
+                i += 2;
+            }
+
+            '}' => {
+                return Err(start + at);
+            }
+
+            '{' => {
+
+                let (colon, end) = match expression_end(&chars, i + 1) {
+                    Some(found) => { found }
+                    None => { return Err(start + at); }
+                };
+
+                if !text.is_empty() {
+                    parts.push(FormatPart::Text(unescape(&text).ok_or(start + at)?));
+                    text.clear();
+                }
+
+                let expression_start = chars[i + 1].0;
+                let expression_end = chars[colon.unwrap_or(end)].0;
+
+                let expression = expression(&body[expression_start..expression_end], start + expression_start)?;
+
+                let spec = match colon {
+                    Some(colon) => {
+                        let spec_start = chars[colon].0 + 1;
+                        spec(&body[spec_start..chars[end].0]).ok_or(start + spec_start)?
+                    }
+                    None => { FormatSpec::default() }
+                };
+
+                parts.push(FormatPart::Value(expression, spec));
+                i = end + 1;
+            }
+
+            _ => {
+                text.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(FormatPart::Text(unescape(&text).ok_or(start)?));
+    }
+
+    Ok(parts)
+}
+
+/// Find the brace that closes an expression starting at the given index, along with the colon
+/// that starts its format spec if there is one. Braces, brackets and quotes within the
+/// expression are skipped over so dictionaries and strings can be used inside of it
+fn expression_end(chars: &[(usize, char)], from: usize) -> Option<(Option<usize>, usize)> {
+
+    let mut depth = 0;
+    let mut quote = None;
+    let mut colon = None;
+    let mut escaped = false;
+
+    for (index, (_, c)) in chars.iter().enumerate().skip(from) {
+
+        if let Some(q) = quote {
+
+            if escaped {
+                escaped = false;
+            } else if *c == '\\' {
+                escaped = true;
+            } else if *c == q {
+                quote = None;
+            }
+            continue;
+        }
+
+        match c {
+            '\'' | '"'      => { quote = Some(*c); }
+            '(' | '[' | '{' => { depth += 1; }
+            ')' | ']'       => { depth -= 1; }
+            '}' if depth == 0 => { return Some((colon, index)); }
+            '}'             => { depth -= 1; }
+            ':' if depth == 0 && colon.is_none() => { colon = Some(index); }
+            _ => { }
+        }
+    }
+
+    None
+}
+
+/// Parse the source of an expression that sits at the given location
+fn expression(source: &str, location: usize) -> Result<Box<Expr>, usize> {
+
+    if source.trim().is_empty() {
+        return Err(location);
+    }
+
+    match ExprParser::new().parse(source) {
+        Ok(mut expression) => {
+            expression.shift(location);
+            Ok(expression)
+        }
+        Err(e) => { Err(location + parse_error_span(&e).start) }
+    }
+}
+
+/// Read a format spec, [[fill]align][0][width][.precision][radix]
+fn spec(source: &str) -> Option<FormatSpec> {
+
+    let chars: Vec<char> = source.chars().collect();
+    let mut spec = FormatSpec::default();
+    let mut i = 0;
+
+    let alignment = |c: char| {
+        match c {
+            '<' => { Some(Alignment::Left) }
+            '>' => { Some(Alignment::Right) }
+            '^' => { Some(Alignment::Center) }
+            _ => { None }
+        }
+    };
+
+    // A fill character is only there if an alignment follows it
+    if let Some(align) = chars.get(1).and_then(|c| alignment(*c)) {
+        spec.fill = chars[0];
+        spec.align = Some(align);
+        i = 2;
+    } else if let Some(align) = chars.first().and_then(|c| alignment(*c)) {
+        spec.align = Some(align);
+        i = 1;
+    }
+
+    if chars.get(i) == Some(&'0') {
+        spec.zero = true;
+        i += 1;
+    }
+
+    spec.width = number(&chars, &mut i);
+
+    if chars.get(i) == Some(&'.') {
+        i += 1;
+        spec.precision = Some(number(&chars, &mut i)?);
+    }
+
+    match chars.get(i) {
+        Some('x') => { spec.radix = Some(16); }
+        Some('X') => { spec.radix = Some(16); spec.uppercase = true; }
+        Some('o') => { spec.radix = Some(8); }
+        Some('b') => { spec.radix = Some(2); }
+        Some(_)   => { return None; }
+        None      => { return Some(spec); }
+    }
+
+    // The radix has to be the last thing in the spec
+    if i + 1 != chars.len() {
+        return None;
+    }
+
+    Some(spec)
+}
+
+/// Read the digits of a number from the spec, if there are any
+fn number(chars: &[char], i: &mut usize) -> Option<usize> {
+
+    let digits: String = chars[*i..].iter().take_while(|c| c.is_ascii_digit()).collect();
+    *i += digits.len();
+
+    digits.parse().ok()
+}
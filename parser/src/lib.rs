@@ -4,4 +4,89 @@ extern crate micron_ast;
 #[macro_use] 
 extern crate lalrpop_util;
 
-lalrpop_mod!(pub micron); // synthesized by LALRPOP
+// The generated parser trips these, as do the boxed AST types the grammar builds
+lalrpop_mod!(
+    #[allow(
+        clippy::clone_on_copy,
+        clippy::just_underscores_and_digits,
+        clippy::needless_lifetimes,
+        clippy::too_many_arguments,
+        clippy::vec_box,
+        clippy::box_collection,
+        clippy::vec_init_then_push
+    )]
+    pub micron
+); // synthesized by LALRPOP
+
+mod format;
+pub use format::format_parts;
+
+
+use lalrpop_util::ParseError;
+use micron_ast::Span;
+
+/// Blank out comments so the source can be handed to the parser. A comment is a line that
+/// starts with two dashes, anywhere else '--' is read as two minus signs. Each comment is 
+/// replaced by spaces so the locations the parser records still line up with the source
+pub fn strip_comments(source: &str) -> String {
+
+    let mut result = String::with_capacity(source.len());
+
+    for line in source.split_inclusive(&['\n', '\r'][..]) {
+
+        if line.trim_start().starts_with("--") {
+            let content = line.trim_end_matches(&['\n', '\r'][..]);
+            result.push_str(&" ".repeat(content.len()));
+            result.push_str(&line[content.len()..]);
+        } else {
+            result.push_str(line);
+        }
+    }
+
+    result
+}
+
+/// Decode the escape sequences in the body of a string literal. The lexer only accepts
+/// known escapes, so the only thing that can fail is a '\u{..}' that isn't a unicode character
+pub fn unescape(body: &str) -> Option<String> {
+
+    let mut result = String::with_capacity(body.len());
+    let mut chars = body.chars();
+
+    while let Some(c) = chars.next() {
+
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next()? {
+            '0' => { result.push('\0'); }
+            'n' => { result.push('\n'); }
+            'r' => { result.push('\r'); }
+            't' => { result.push('\t'); }
+            'u' => {
+
+                // \u{XXXX}, the braces are guaranteed by the lexer
+                let digits: String = chars.by_ref().skip(1).take_while(|c| *c != '}').collect();
+                let code = u32::from_str_radix(&digits, 16).ok()?;
+                result.push(std::char::from_u32(code)?);
+            }
+            other => { result.push(other); }
+        }
+    }
+
+    Some(result)
+}
+
+/// Where in the source a parse error was found
+pub fn parse_error_span<T, E>(error: &ParseError<usize, T, E>) -> Span {
+
+    match error {
+        ParseError::InvalidToken { location }         => { Span::new(*location, *location + 1) }
+        ParseError::UnrecognizedEOF { location, .. }  => { Span::new(*location, *location + 1) }
+        ParseError::UnrecognizedToken { token, .. }   => { Span::new(token.0, token.2) }
+        ParseError::ExtraToken { token }              => { Span::new(token.0, token.2) }
+        ParseError::User { .. }                       => { Span::default() }
+    }
+}
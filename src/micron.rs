@@ -5,6 +5,20 @@ extern crate clap;
 use clap::{Arg, App};
 
 fn main() {
+
+    // Functions recurse through the stack of the tree walking engine, so give it enough 
+    // room to reach the maximum call depth
+    let runner = std::thread::Builder::new()
+                    .stack_size(micron_engine::STACK_SIZE)
+                    .spawn(run)
+                    .unwrap();
+
+    if runner.join().is_err() {
+        std::process::exit(1);
+    }
+}
+
+fn run() {
     let matches = App::new("Micron")
                           .version("0.1.0")
                           .author("Josh A. Bosley <bosley117@gmail.com>")
@@ -13,6 +27,16 @@ fn main() {
                                .help("Sets the input file to use")
                                .required(true)
                                .index(1))
+                          .arg(Arg::with_name("tree-walker")
+                               .long("tree-walker")
+                               .help("Run with the tree walking engine instead of the virtual machine"))
+                          .arg(Arg::with_name("precision")
+                               .long("precision")
+                               .takes_value(true)
+                               .help("Sets the precision, in bits, that floats are read in and promoted at"))
+                          .arg(Arg::with_name("auto-vivify")
+                               .long("auto-vivify")
+                               .help("Create missing dictionaries along the way when assigning to a nested variable"))
                           .get_matches();
 
 
@@ -21,26 +45,84 @@ fn main() {
     let import_result = match micron_file_import::import(matches.value_of("file").unwrap()) {
         Ok(result) => { result },
         Err(e)     => {
-            panic!("File Error: {:?}", e);
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
     };
 
     //println!("Got: {:?}", import_result);
 
-    execute(import_result);
+    let file = matches.value_of("file").unwrap();
+
+    let precision = match matches.value_of("precision") {
+        Some(bits) => {
+            match bits.parse::<u32>() {
+                Ok(bits) => { Some(bits) }
+                Err(_)   => {
+                    eprintln!("Invalid precision '{}', expected a number of bits", bits);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => { None }
+    };
+
+    if matches.is_present("tree-walker") {
+
+        let mut engine = micron_engine::Engine::new();
+        engine.set_echo(true);
+
+        if let Some(bits) = precision {
+            set_precision(engine.set_precision(bits));
+        }
+
+        engine.set_auto_vivify(matches.is_present("auto-vivify"));
+
+        execute(file, import_result, |statement| {
+            engine.execute_statement(statement).map(|e| (e, engine.error_location()))
+        });
+
+    } else {
+
+        let mut vm = micron_engine::Vm::new();
+        vm.set_echo(true);
+
+        if let Some(bits) = precision {
+            set_precision(vm.set_precision(bits));
+        }
+
+        vm.set_auto_vivify(matches.is_present("auto-vivify"));
+
+        execute(file, import_result, |statement| {
+            vm.execute_statement(statement).map(|e| (e, vm.error_location()))
+        });
+    }
 }
 
-// Take the statements read in from the importer and execute them
-fn execute(imported_statements: micron_file_import::ImportResult) {
+// Bail out if the precision given on the command line was refused by the backend
+fn set_precision(result: Result<(), micron_engine::ExecutionError>) {
 
-    let mut engine  = micron_engine::Engine::new();
+    if let Err(e) = result {
+        eprintln!("Invalid precision : {}", e);
+        std::process::exit(1);
+    }
+}
+
+// Take the statements read in from the importer and execute them one at a time with the given backend
+fn execute<F>(file: &str, imported_statements: micron_file_import::ImportResult, mut execute_statement: F) 
+    where F: FnMut(micron_ast::Statement) -> Option<(micron_engine::ExecutionError, Option<micron_ast::Span>)> {
 
     for statement in imported_statements.statements {
 
-        if let Some(e) = engine.execute_statement(*statement) {
+        if let Some((e, location)) = execute_statement(*statement) {
 
-            panic!("Execution Error : {}", e);
+            let message = format!("Execution Error : {}", e);
+
+            match location {
+                Some(span) => { eprintln!("{}", span.render(file, &imported_statements.source, &message)); }
+                None       => { eprintln!("{}", message); }
+            }
+            std::process::exit(1);
         }
     }
-
-}
\ No newline at end of file
+}
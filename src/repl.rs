@@ -1,5 +1,6 @@
 
 extern crate micron_engine;
+extern crate micron_file_import;
 
 use micron_engine::Engine;
 
@@ -7,7 +8,16 @@ use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
 fn main() {
-   repl();
+
+   // Give the engine enough stack to reach the maximum call depth
+   let runner = std::thread::Builder::new()
+                   .stack_size(micron_engine::STACK_SIZE)
+                   .spawn(repl)
+                   .unwrap();
+
+   if runner.join().is_err() {
+       std::process::exit(1);
+   }
 }
 
 fn repl_banner() {
@@ -27,6 +37,7 @@ fn repl() {
     repl_banner();
 
     let mut engine  = Engine::new();
+    engine.set_echo(true);
 
     let mut rl = Editor::<()>::new();
     if rl.load_history("repl-history.txt").is_err() {
@@ -40,7 +51,9 @@ fn repl() {
             Ok(line) => {
                 rl.add_history_entry(line.as_str());
 
-                match micron_parser::micron::ProgramParser::new().parse(&line) {
+                let stripped = micron_parser::strip_comments(&line);
+
+                match micron_parser::micron::ProgramParser::new().parse(&stripped) {
 
                     Ok(statements)  => { 
                         
@@ -48,12 +61,18 @@ fn repl() {
             
                             if let Some(e) = engine.execute_statement(*x) {
 
-                                println!("Error: {}", e);
+                                let message = format!("Error: {}", e);
+
+                                match engine.error_location() {
+                                    Some(span) => { println!("{}", span.render("<repl>", &line, &message)); }
+                                    None       => { println!("{}", message); }
+                                }
+                                break;
                             }
                         } 
                     }
                     Err(e) => { 
-                        println!("Error >>> {}", e);
+                        println!("{}", micron_file_import::ParseFailure::new("<repl>", &line, &e));
                         continue;
                     }
                 };